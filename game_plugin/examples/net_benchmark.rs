@@ -0,0 +1,169 @@
+// Streams snapshots of moving players from a server to a client over the loopback
+// transport and compares the traffic of full and delta compressed snapshots.
+//
+//     cargo run -p game_plugin --example net_benchmark -- [players] [seconds] [loss percent]
+
+use game_plugin::net::{
+    decode, encode, ConditionedTransport, LoopbackTransport, NetworkConditions, PlayerState,
+    RoundState, ServerMessage, SnapshotDelta, Transport, WorldSnapshot,
+};
+use game_plugin::{Animation, RoundPhase, Team};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+const SNAPSHOT_RATE: u32 = 20;
+const HISTORY: usize = 32;
+
+fn arg<T: std::str::FromStr>(index: usize, default: T) -> T {
+    std::env::args()
+        .nth(index)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(default)
+}
+
+fn spawn_players(count: u32) -> Vec<PlayerState> {
+    (1..=count)
+        .map(|id| PlayerState {
+            id,
+            position: [0.0, 0.0],
+            flip_x: false,
+            animation: Animation::Idle,
+            team: Some(if id % 2 == 0 {
+                Team::Terrorists
+            } else {
+                Team::CounterTerrorists
+            }),
+            health: 100.0,
+            dead: false,
+        })
+        .collect()
+}
+
+// About half the players move in any snapshot, the rest stand still
+fn simulate(players: &mut [PlayerState], rng: &mut impl Rng) {
+    for player in players.iter_mut() {
+        if rng.gen_bool(0.5) {
+            player.position[0] += rng.gen_range(-8.0..8.0);
+            player.position[1] += rng.gen_range(-8.0..8.0);
+            player.flip_x = player.position[0] > 0.0;
+            player.animation = Animation::Walk;
+        } else {
+            player.animation = Animation::Idle;
+        }
+        if rng.gen_bool(0.01) {
+            player.health = (player.health - 25.0).max(0.0);
+        }
+    }
+}
+
+fn run(players: u32, seconds: u32, packet_loss: f32, compress: bool) -> (usize, u32) {
+    let server_addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let client_addr: SocketAddr = "127.0.0.1:2".parse().unwrap();
+    let (server, client) = LoopbackTransport::pair(server_addr, client_addr);
+    let conditions = NetworkConditions {
+        packet_loss,
+        ..Default::default()
+    };
+    let mut server = ConditionedTransport::new(Box::new(server), conditions.clone());
+    let mut client = ConditionedTransport::new(Box::new(client), conditions);
+
+    let mut rng = rand::thread_rng();
+    let mut state = spawn_players(players);
+    let mut server_history: VecDeque<WorldSnapshot> = VecDeque::new();
+    let mut client_history: VecDeque<WorldSnapshot> = VecDeque::new();
+    let mut acked = 0;
+    let mut bytes = 0;
+    let mut decoded = 0;
+
+    for tick in 1..=seconds * SNAPSHOT_RATE {
+        simulate(&mut state, &mut rng);
+        let snapshot = WorldSnapshot {
+            tick,
+            players: state.clone(),
+            round: RoundState {
+                phase: RoundPhase::Live,
+                seconds_left: 115.0 - tick as f32 / SNAPSHOT_RATE as f32,
+                terrorist_score: 0,
+                counter_terrorist_score: 0,
+            },
+        };
+        let baseline = server_history
+            .iter()
+            .find(|old| compress && old.tick == acked);
+        let message = encode(&ServerMessage::Snapshot(SnapshotDelta::between(
+            baseline, &snapshot, 0,
+        )));
+        bytes += message.len();
+        server.send(client_addr, &message);
+        server_history.push_back(snapshot);
+        if server_history.len() > HISTORY {
+            server_history.pop_front();
+        }
+
+        // The client acknowledges every snapshot it could decode, the ack may get lost as well
+        while let Some((_, payload)) = client.receive() {
+            let delta = match decode::<ServerMessage>(&payload) {
+                Some(ServerMessage::Snapshot(delta)) => delta,
+                _ => continue,
+            };
+            let baseline = match delta.baseline {
+                0 => None,
+                tick => match client_history.iter().find(|old| old.tick == tick) {
+                    Some(baseline) => Some(baseline),
+                    None => continue,
+                },
+            };
+            if let Some(snapshot) = delta.apply(baseline) {
+                decoded += 1;
+                client.send(server_addr, &snapshot.tick.to_le_bytes());
+                client_history.push_back(snapshot);
+                if client_history.len() > HISTORY {
+                    client_history.pop_front();
+                }
+            }
+        }
+        while let Some((_, payload)) = server.receive() {
+            let mut tick = [0; 4];
+            tick.copy_from_slice(&payload[..4]);
+            acked = acked.max(u32::from_le_bytes(tick));
+        }
+    }
+    (bytes, decoded)
+}
+
+fn main() {
+    let players = arg(1, 10);
+    let seconds = arg(2, 60);
+    let packet_loss = arg(3, 0.0) / 100.0;
+
+    let total = seconds * SNAPSHOT_RATE;
+    let (full, full_decoded) = run(players, seconds, packet_loss, false);
+    let (delta, delta_decoded) = run(players, seconds, packet_loss, true);
+    println!(
+        "{} players, {} snapshots, {:.0}% loss",
+        players,
+        total,
+        packet_loss * 100.0
+    );
+    for (name, bytes, decoded) in [
+        ("full", full, full_decoded),
+        ("delta", delta, delta_decoded),
+    ]
+    .iter()
+    {
+        println!(
+            "{:>6}: {:>9} bytes, {:>6.2} kB/s, {:>5.1} bytes/snapshot, {}/{} decoded",
+            name,
+            bytes,
+            *bytes as f32 / seconds as f32 / 1024.0,
+            *bytes as f32 / total as f32,
+            decoded,
+            total
+        );
+    }
+    println!(
+        "delta snapshots use {:.1}% of the full size",
+        100.0 * delta as f32 / full.max(1) as f32
+    );
+}
@@ -1,4 +1,7 @@
+use crate::camera::MainCamera;
+use crate::settings::{Controls, Settings};
 use crate::GameState;
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 
 pub struct ActionsPlugin;
@@ -8,7 +11,12 @@ pub struct ActionsPlugin;
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<Actions>().add_system_set(
-            SystemSet::on_update(GameState::Playing).with_system(set_movement_actions.system()),
+            SystemSet::on_update(GameState::Playing)
+                .with_system(set_movement_actions.system())
+                .with_system(set_button_actions.system())
+                .with_system(set_fire_action.system())
+                .with_system(set_aim_action.system())
+                .with_system(set_zoom_action.system()),
         );
     }
 }
@@ -16,19 +24,83 @@ impl Plugin for ActionsPlugin {
 #[derive(Default)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
+    pub use_held: bool,
+    // Holding down makes movement quieter
+    pub sneak: bool,
+    pub toggle_buy_menu: bool,
+    pub throw_grenade: bool,
+    // The scoreboard shows while this is held
+    pub show_scoreboard: bool,
+    pub fire: bool,
+    // Cursor position in world coordinates
+    pub aim: Option<Vec2>,
+    // Lines the mouse wheel scrolled this frame, positive when scrolling up
+    pub zoom: f32,
 }
 
-fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    if GameControl::Up.just_released(&keyboard_input)
-        || GameControl::Up.pressed(&keyboard_input)
-        || GameControl::Left.just_released(&keyboard_input)
-        || GameControl::Left.pressed(&keyboard_input)
-        || GameControl::Down.just_released(&keyboard_input)
-        || GameControl::Down.pressed(&keyboard_input)
-        || GameControl::Right.just_released(&keyboard_input)
-        || GameControl::Right.pressed(&keyboard_input)
-        || GameControl::Space.just_released(&keyboard_input)
-        || GameControl::Space.pressed(&keyboard_input)
+fn set_button_actions(
+    mut actions: ResMut<Actions>,
+    settings: Res<Settings>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    let controls = &settings.controls;
+    actions.use_held = GameControl::Use.pressed(controls, &keyboard_input);
+    actions.sneak = GameControl::Down.pressed(controls, &keyboard_input);
+    actions.toggle_buy_menu = GameControl::Buy.just_pressed(controls, &keyboard_input);
+    actions.throw_grenade = GameControl::Throw.just_pressed(controls, &keyboard_input);
+    actions.show_scoreboard = GameControl::Scoreboard.pressed(controls, &keyboard_input);
+}
+
+fn set_fire_action(mut actions: ResMut<Actions>, mouse_input: Res<Input<MouseButton>>) {
+    actions.fire = mouse_input.pressed(MouseButton::Left);
+}
+
+fn set_aim_action(
+    mut actions: ResMut<Actions>,
+    windows: Res<Windows>,
+    cameras: Query<&Transform, With<MainCamera>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    actions.aim = match (window.cursor_position(), cameras.single()) {
+        (Some(cursor), Ok(camera)) => {
+            let size = Vec2::new(window.width(), window.height());
+            let world = camera.compute_matrix() * (cursor - 0.5 * size).extend(0.0).extend(1.0);
+            Some(world.truncate().truncate())
+        }
+        _ => None,
+    };
+}
+
+fn set_zoom_action(mut actions: ResMut<Actions>, mut wheel: EventReader<MouseWheel>) {
+    actions.zoom = wheel
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            // Touchpads scroll in pixels, a line is roughly 20 of them
+            MouseScrollUnit::Pixel => event.y / 20.0,
+        })
+        .sum();
+}
+
+fn set_movement_actions(
+    mut actions: ResMut<Actions>,
+    settings: Res<Settings>,
+    keyboard_input: Res<Input<KeyCode>>,
+) {
+    let controls = &settings.controls;
+    if GameControl::Up.just_released(controls, &keyboard_input)
+        || GameControl::Up.pressed(controls, &keyboard_input)
+        || GameControl::Left.just_released(controls, &keyboard_input)
+        || GameControl::Left.pressed(controls, &keyboard_input)
+        || GameControl::Down.just_released(controls, &keyboard_input)
+        || GameControl::Down.pressed(controls, &keyboard_input)
+        || GameControl::Right.just_released(controls, &keyboard_input)
+        || GameControl::Right.pressed(controls, &keyboard_input)
+        || GameControl::Space.just_released(controls, &keyboard_input)
+        || GameControl::Space.pressed(controls, &keyboard_input)
     {
         let mut player_movement = Vec2::ZERO;
 
@@ -54,28 +126,28 @@ fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<
         //     player_movement.y = actions.player_movement.unwrap_or(Vec2::ZERO).y;
         // }
 
-        if GameControl::Space.just_released(&keyboard_input){
-            if GameControl::Space.pressed(&keyboard_input){
+        if GameControl::Space.just_released(controls, &keyboard_input){
+            if GameControl::Space.pressed(controls, &keyboard_input){
                 player_movement.y = 1.;
             }
-        } else if GameControl::Space.just_pressed(&keyboard_input){
+        } else if GameControl::Space.just_pressed(controls, &keyboard_input){
             player_movement.y = 1.;
         }
 
 
-        if GameControl::Right.just_released(&keyboard_input)
-            || GameControl::Left.just_released(&keyboard_input)
+        if GameControl::Right.just_released(controls, &keyboard_input)
+            || GameControl::Left.just_released(controls, &keyboard_input)
         {
-            if GameControl::Right.pressed(&keyboard_input) {
+            if GameControl::Right.pressed(controls, &keyboard_input) {
                 player_movement.x = 1.;
-            } else if GameControl::Left.pressed(&keyboard_input) {
+            } else if GameControl::Left.pressed(controls, &keyboard_input) {
                 player_movement.x = -1.;
             } else {
                 player_movement.x = 0.;
             }
-        } else if GameControl::Right.just_pressed(&keyboard_input) {
+        } else if GameControl::Right.just_pressed(controls, &keyboard_input) {
             player_movement.x = 1.;
-        } else if GameControl::Left.just_pressed(&keyboard_input) {
+        } else if GameControl::Left.just_pressed(controls, &keyboard_input) {
             player_movement.x = -1.;
         } else {
             player_movement.x = actions.player_movement.unwrap_or(Vec2::ZERO).x;
@@ -96,73 +168,47 @@ enum GameControl {
     Left,
     Right,
     Space,
+    Use,
+    Buy,
+    Throw,
+    Scoreboard,
 }
 
 impl GameControl {
-    fn just_released(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    // Movement keys can be rebound, the arrow keys always work as well
+    fn keys(&self, controls: &Controls) -> Vec<KeyCode> {
         match self {
-            GameControl::Up => {
-                keyboard_input.just_released(KeyCode::W)
-                    || keyboard_input.just_released(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_released(KeyCode::S)
-                    || keyboard_input.just_released(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_released(KeyCode::A)
-                    || keyboard_input.just_released(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_released(KeyCode::D)
-                    || keyboard_input.just_released(KeyCode::Right)
-            }
-            GameControl::Space => { 
-                keyboard_input.just_released(KeyCode::Space)
-            }
+            GameControl::Up => vec![controls.up, KeyCode::Up],
+            GameControl::Down => vec![controls.down, KeyCode::Down],
+            GameControl::Left => vec![controls.left, KeyCode::Left],
+            GameControl::Right => vec![controls.right, KeyCode::Right],
+            GameControl::Space => vec![controls.jump],
+            GameControl::Use => vec![controls.interact],
+            GameControl::Buy => vec![controls.buy],
+            GameControl::Throw => vec![controls.throw],
+            GameControl::Scoreboard => vec![controls.scoreboard],
         }
     }
 
-    fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
-            GameControl::Space => { 
-                keyboard_input.pressed(KeyCode::Space)
-            }
-        }
+    fn just_released(&self, controls: &Controls, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        self.keys(controls)
+            .iter()
+            .any(|key| keyboard_input.just_released(*key))
     }
 
-    fn just_pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    fn pressed(&self, controls: &Controls, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+        self.keys(controls)
+            .iter()
+            .any(|key| keyboard_input.pressed(*key))
+    }
+
+    fn just_pressed(&self, controls: &Controls, keyboard_input: &Res<Input<KeyCode>>) -> bool {
         match self {
-            GameControl::Up => {
-                keyboard_input.just_pressed(KeyCode::W) || keyboard_input.just_pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_pressed(KeyCode::S)
-                    || keyboard_input.just_pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_pressed(KeyCode::A)
-                    || keyboard_input.just_pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_pressed(KeyCode::D)
-                    || keyboard_input.just_pressed(KeyCode::Right)
-            }
-            GameControl::Space => { 
-                keyboard_input.pressed(KeyCode::Space)
-            }
+            GameControl::Space => self.pressed(controls, keyboard_input),
+            _ => self
+                .keys(controls)
+                .iter()
+                .any(|key| keyboard_input.just_pressed(*key)),
         }
     }
 }
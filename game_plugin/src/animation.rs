@@ -0,0 +1,378 @@
+use crate::audio::{Sound, SoundEvent};
+use crate::combat::Dead;
+use crate::consts::{MUZZLE_FLASH_SIZE, MUZZLE_FLASH_TIME, PLAYER_TILE_SIZE};
+use crate::economy::Inventory;
+use crate::loading::{AnimationAsset, AsepriteAsset, DataAssets, SheetAssets};
+use crate::net::{network_mode, Interpolation};
+use crate::player::{facing, LastMovement, PlayerInput};
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct AnimationPlugin;
+
+// Players pick their animation from how they moved and what they are doing, the clips
+// themselves are set in assets/data/player.anims.csv. Their frames and timing come from
+// the Aseprite files. Remote players on a client play whatever the server sends instead.
+// Clips can fire AnimationEvents on their frames, so sounds and gameplay follow the art.
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mode = network_mode(app);
+
+        app.add_event::<AnimationEvent>().add_system_set(
+            gameplay_update(Role::Everyone)
+                .with_system(choose_animation.system())
+                .with_system(anim_player.system()),
+        );
+        if !mode.is_headless() {
+            app.init_resource::<MuzzleFlashMaterial>()
+                .add_system_set(
+                    gameplay_update(Role::Everyone)
+                        .with_system(spawn_muzzle_flashes.system())
+                        .with_system(fade_muzzle_flashes.system()),
+                )
+                .add_system_set(
+                    SystemSet::on_exit(GameState::Playing)
+                        .with_system(remove_muzzle_flashes.system()),
+                );
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum Animation {
+    Idle,
+    Walk,
+    Jump,
+    Fall,
+    Land,
+    Crouch,
+    Shoot,
+    Reload,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameEvent {
+    Footstep,
+    ReloadComplete,
+}
+
+impl FrameEvent {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Footstep" => Some(FrameEvent::Footstep),
+            "ReloadComplete" => Some(FrameEvent::ReloadComplete),
+            _ => None,
+        }
+    }
+}
+
+// A player's animation reached a frame carrying `event`
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub event: FrameEvent,
+}
+
+// The sprite sheets clips take their frames from
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Sheet {
+    Stay,
+    Walk,
+    Jump,
+}
+
+impl Sheet {
+    fn handle<'a>(&self, sheets: &'a SheetAssets) -> &'a Handle<AsepriteAsset> {
+        match self {
+            Sheet::Stay => &sheets.player_stay,
+            Sheet::Walk => &sheets.player_walk,
+            Sheet::Jump => &sheets.player_jump,
+        }
+    }
+}
+
+// `anim` is the animation the player should show, the clip playing switches to it
+// in the next anim_player run
+pub struct PlayerAnim {
+    pub anim: Animation,
+    playing: Option<Animation>,
+    frame: usize,
+    elapsed: f32,
+    finished: bool,
+}
+
+impl Default for PlayerAnim {
+    fn default() -> Self {
+        Self {
+            anim: Animation::Idle,
+            playing: None,
+            frame: 0,
+            elapsed: 0.0,
+            finished: false,
+        }
+    }
+}
+
+impl PlayerAnim {
+    // Whether the clip of the current animation played to its end, looping clips never do
+    pub fn finished(&self) -> bool {
+        self.finished && self.playing == Some(self.anim)
+    }
+}
+
+// Jumping and falling win over everything else. A landing plays once before the player
+// can walk again, reloading, shooting and crouching only show on the ground.
+// Sneaking players walk normally, their footsteps are just quieter.
+fn next_animation(
+    current: Animation,
+    finished: bool,
+    moved: Vec3,
+    input: &PlayerInput,
+    reloading: bool,
+) -> Animation {
+    if moved.y > 0.0 {
+        Animation::Jump
+    } else if moved.y < 0.0 {
+        Animation::Fall
+    } else if current == Animation::Fall || (current == Animation::Land && !finished) {
+        Animation::Land
+    } else if reloading {
+        Animation::Reload
+    } else if input.fire {
+        Animation::Shoot
+    } else if moved.x != 0.0 {
+        Animation::Walk
+    } else if input.sneak {
+        Animation::Crouch
+    } else {
+        Animation::Idle
+    }
+}
+
+// Runs for every player simulated here, remote players on a client are interpolated
+fn choose_animation(
+    mut players: Query<
+        (
+            &LastMovement,
+            &PlayerInput,
+            Option<&Inventory>,
+            &mut PlayerAnim,
+            &mut TextureAtlasSprite,
+        ),
+        (Without<Dead>, Without<Interpolation>),
+    >,
+) {
+    for (movement, input, inventory, mut anim, mut sprite) in players.iter_mut() {
+        let moved = movement.0;
+        // Sprites face left, they are flipped while moving right
+        if moved.x != 0.0 {
+            sprite.flip_x = moved.x > 0.0;
+        }
+        let reloading = inventory
+            .and_then(|inventory| inventory.weapon.as_ref())
+            .map_or(false, |weapon| weapon.reloading);
+        anim.anim = next_animation(anim.anim, anim.finished(), moved, input, reloading);
+    }
+}
+
+// Switches to the clip of a changed animation and advances the current one.
+// Looping clips start over, the others stay on their last frame.
+// Entering a frame, also when a clip starts or loops, fires its events.
+#[allow(clippy::too_many_arguments)]
+fn anim_player(
+    mut commands: Commands,
+    mut events: EventWriter<AnimationEvent>,
+    time: Res<Time>,
+    sheet_assets: Res<SheetAssets>,
+    data: Res<DataAssets>,
+    animations: Res<Assets<AnimationAsset>>,
+    sheets: Res<Assets<AsepriteAsset>>,
+    mut query: Query<(Entity, &mut TextureAtlasSprite, &mut PlayerAnim)>,
+) {
+    let clips = match animations.get(&data.animations) {
+        Some(animations) => &animations.clips,
+        None => return,
+    };
+    for (entity, mut sprite, mut anim) in query.iter_mut() {
+        let clip = match clips.get(&anim.anim) {
+            Some(clip) => clip,
+            None => continue,
+        };
+        let sheet = match sheets.get(clip.sheet.handle(&sheet_assets)) {
+            Some(sheet) => sheet,
+            None => continue,
+        };
+        let frames = match sheet.frames(&clip.frames) {
+            Some(frames) => frames,
+            None => continue,
+        };
+        if anim.playing != Some(anim.anim) {
+            anim.playing = Some(anim.anim);
+            anim.frame = 0;
+            anim.elapsed = 0.0;
+            anim.finished = false;
+            sprite.index = frames.start as u32;
+            commands.entity(entity).insert(sheet.atlas.clone());
+            send_frame_events(&mut events, entity, &clip.events, 0);
+            continue;
+        }
+        if anim.finished {
+            continue;
+        }
+
+        // Frames without a duration would never end, they last one update
+        let durations = &sheet.durations[frames.clone()];
+        anim.elapsed += time.delta_seconds();
+        while anim.elapsed >= durations[anim.frame] {
+            anim.elapsed -= durations[anim.frame];
+            if anim.frame + 1 < durations.len() {
+                anim.frame += 1;
+            } else if clip.looping {
+                anim.frame = 0;
+            } else {
+                anim.finished = true;
+                break;
+            }
+            send_frame_events(&mut events, entity, &clip.events, anim.frame);
+            if durations[anim.frame] <= 0.0 {
+                anim.elapsed = 0.0;
+                break;
+            }
+        }
+        sprite.index = (frames.start + anim.frame) as u32;
+    }
+}
+
+fn send_frame_events(
+    events: &mut EventWriter<AnimationEvent>,
+    entity: Entity,
+    clip_events: &[(usize, FrameEvent)],
+    frame: usize,
+) {
+    for (_, event) in clip_events.iter().filter(|(at, _)| *at == frame) {
+        events.send(AnimationEvent {
+            entity,
+            event: *event,
+        });
+    }
+}
+
+struct MuzzleFlashMaterial(Handle<ColorMaterial>);
+
+impl FromWorld for MuzzleFlashMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        MuzzleFlashMaterial(materials.add(Color::rgb(1.0, 0.85, 0.4).into()))
+    }
+}
+
+struct MuzzleFlash(Timer);
+
+// Every shot flashes, a client learns about them from the fire sounds the server relays.
+// The flash shows in front of the player, on the side it faces.
+fn spawn_muzzle_flashes(
+    mut commands: Commands,
+    material: Res<MuzzleFlashMaterial>,
+    mut sounds: EventReader<SoundEvent>,
+    players: Query<(&Transform, &TextureAtlasSprite)>,
+) {
+    for event in sounds.iter() {
+        let shooter = match (event.sound, event.source) {
+            (Sound::Fire, Some(shooter)) => shooter,
+            _ => continue,
+        };
+        let (transform, sprite) = match players.get(shooter) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        let position = transform.translation.truncate() + facing(sprite) * 0.5 * PLAYER_TILE_SIZE;
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.0.clone(),
+                sprite: Sprite::new(Vec2::splat(MUZZLE_FLASH_SIZE)),
+                transform: Transform::from_translation(position.extend(3.0)),
+                ..Default::default()
+            })
+            .insert(MuzzleFlash(Timer::from_seconds(MUZZLE_FLASH_TIME, false)));
+    }
+}
+
+fn fade_muzzle_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut MuzzleFlash)>,
+) {
+    for (entity, mut flash) in flashes.iter_mut() {
+        if flash.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn remove_muzzle_flashes(mut commands: Commands, flashes: Query<Entity, With<MuzzleFlash>>) {
+    for entity in flashes.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(current: Animation, finished: bool, moved: Vec3, input: &PlayerInput) -> Animation {
+        next_animation(current, finished, moved, input, false)
+    }
+
+    #[test]
+    fn airborne_wins_over_everything() {
+        let input = PlayerInput {
+            fire: true,
+            ..Default::default()
+        };
+        let up = Vec3::new(1.0, 1.0, 0.0);
+        let down = Vec3::new(1.0, -1.0, 0.0);
+        assert_eq!(
+            next_animation(Animation::Reload, false, up, &input, true),
+            Animation::Jump
+        );
+        assert_eq!(next(Animation::Jump, false, down, &input), Animation::Fall);
+    }
+
+    #[test]
+    fn landing_plays_before_walking_again() {
+        let input = PlayerInput::default();
+        let walk = Vec3::new(1.0, 0.0, 0.0);
+        assert_eq!(next(Animation::Fall, false, walk, &input), Animation::Land);
+        assert_eq!(next(Animation::Land, false, walk, &input), Animation::Land);
+        assert_eq!(next(Animation::Land, true, walk, &input), Animation::Walk);
+    }
+
+    #[test]
+    fn ground_animations_by_priority() {
+        let idle = PlayerInput::default();
+        let firing = PlayerInput {
+            fire: true,
+            ..Default::default()
+        };
+        let sneaking = PlayerInput {
+            sneak: true,
+            ..Default::default()
+        };
+        let still = Vec3::ZERO;
+        let walk = Vec3::new(-1.0, 0.0, 0.0);
+        assert_eq!(
+            next_animation(Animation::Idle, true, walk, &firing, true),
+            Animation::Reload
+        );
+        assert_eq!(next(Animation::Walk, true, walk, &firing), Animation::Shoot);
+        assert_eq!(
+            next(Animation::Idle, true, walk, &sneaking),
+            Animation::Walk
+        );
+        assert_eq!(
+            next(Animation::Walk, true, still, &sneaking),
+            Animation::Crouch
+        );
+        assert_eq!(next(Animation::Walk, true, still, &idle), Animation::Idle);
+    }
+}
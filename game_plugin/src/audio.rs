@@ -0,0 +1,188 @@
+use crate::camera::MainCamera;
+use crate::combat::Dead;
+use crate::consts::{HEARING_RANGE, OCCLUSION_PER_TILE, PANNING_DISTANCE, SOUND_CHANNELS};
+use crate::loading::{AudioAssets, SoundAsset};
+use crate::map::{Map, TileType};
+use crate::net::network_mode;
+use crate::player::LocalPlayer;
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy_kira_audio::{Audio, AudioChannel, AudioPlugin};
+use serde::{Deserialize, Serialize};
+
+pub struct InternalAudioPlugin;
+
+// Gameplay systems send SoundEvents instead of playing audio themselves, this plugin
+// plays them. Effects and interface sounds have their own volume in the settings. Sounds
+// in the world are panned and attenuated relative to the local player, walls in between
+// muffle them.
+impl Plugin for InternalAudioPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mode = network_mode(app);
+
+        app.add_event::<SoundEvent>();
+        // A dedicated server still sends sound events, its clients play them
+        if mode.is_headless() {
+            return;
+        }
+        app.add_plugin(AudioPlugin)
+            .init_resource::<AudioChannels>()
+            .add_system(play_sounds.system());
+    }
+}
+
+// Which files play for a sound is set in assets/data/audio.sounds.csv, optionally per
+// surface the player stands on. Sounds without a row there stay silent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Sound {
+    Jump,
+    Land,
+    Footstep,
+    Fire,
+    Hit,
+    Death,
+    Select,
+}
+
+impl Sound {
+    pub fn channel(&self) -> SoundChannel {
+        match self {
+            Sound::Select => SoundChannel::Interface,
+            _ => SoundChannel::Effects,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundChannel {
+    Effects,
+    Interface,
+}
+
+pub struct SoundEvent {
+    pub sound: Sound,
+    // Where in the world the sound comes from, None for the interface
+    pub position: Option<Vec2>,
+    // The player making the sound
+    pub source: Option<Entity>,
+    // The tile under the player's feet for movement sounds
+    pub surface: Option<TileType>,
+    // 1 for a normal sound, sneaking players are quieter
+    pub loudness: f32,
+}
+
+impl SoundEvent {
+    pub fn at(sound: Sound, position: Vec2, source: Entity) -> Self {
+        Self {
+            sound,
+            position: Some(position),
+            source: Some(source),
+            surface: None,
+            loudness: 1.0,
+        }
+    }
+
+    pub fn on_surface(mut self, surface: Option<TileType>) -> Self {
+        self.surface = surface;
+        self
+    }
+
+    pub fn with_loudness(mut self, loudness: f32) -> Self {
+        self.loudness = loudness;
+        self
+    }
+
+    pub fn interface(sound: Sound) -> Self {
+        Self {
+            sound,
+            position: None,
+            source: None,
+            surface: None,
+            loudness: 1.0,
+        }
+    }
+}
+
+// Volume and panning belong to a channel in kira, so every sound rotates through a few
+// channels of its own. A new sound only changes the one that was played longest ago.
+pub struct AudioChannels {
+    channels: Vec<AudioChannel>,
+    next: usize,
+}
+
+impl Default for AudioChannels {
+    fn default() -> Self {
+        Self {
+            channels: (0..SOUND_CHANNELS)
+                .map(|index| AudioChannel::new(format!("sound{}", index)))
+                .collect(),
+            next: 0,
+        }
+    }
+}
+
+impl AudioChannels {
+    fn next(&mut self) -> &AudioChannel {
+        let index = self.next;
+        self.next = (index + 1) % self.channels.len();
+        &self.channels[index]
+    }
+}
+
+// How well a sound at `source` is heard at `listener`, from 0 to 1. Bots listen the same way.
+pub fn audibility(source: Vec2, listener: Vec2, map: Option<&Map>) -> f32 {
+    let distance = source.distance(listener);
+    let mut volume = (1.0 - distance / HEARING_RANGE).max(0.0);
+    if let Some(map) = map {
+        let walls = map.solid_tiles_between(listener, source);
+        volume *= OCCLUSION_PER_TILE.powi(walls as i32);
+    }
+    volume
+}
+
+// Volume factor and panning (0 left, 1 right) of a sound at `source` heard at `listener`
+fn spatialize(source: Vec2, listener: Vec2, map: Option<&Map>) -> (f32, f32) {
+    let panning = (0.5 + 0.5 * (source.x - listener.x) / PANNING_DISTANCE).clamp(0.0, 1.0);
+    (audibility(source, listener, map), panning)
+}
+
+// The listener is the local player while alive, otherwise wherever the camera looks
+#[allow(clippy::too_many_arguments)]
+fn play_sounds(
+    audio: Res<Audio>,
+    settings: Res<Settings>,
+    map: Option<Res<Map>>,
+    mut channels: ResMut<AudioChannels>,
+    audio_assets: Option<Res<AudioAssets>>,
+    sound_assets: Res<Assets<SoundAsset>>,
+    mut events: EventReader<SoundEvent>,
+    players: Query<&Transform, (With<LocalPlayer>, Without<Dead>)>,
+    cameras: Query<&Transform, With<MainCamera>>,
+) {
+    let table = audio_assets.and_then(|assets| sound_assets.get(&assets.sounds));
+    let listener = players
+        .single()
+        .or_else(|_| cameras.single())
+        .map(|transform| transform.translation.truncate())
+        .ok();
+
+    for event in events.iter() {
+        let file = match table.and_then(|table| table.pick(event.sound, event.surface)) {
+            Some(file) => file,
+            None => continue,
+        };
+        let volume = file.volume * event.loudness * settings.audio.volume(event.sound.channel());
+        let (audibility, panning) = match (event.position, listener) {
+            (Some(position), Some(listener)) => spatialize(position, listener, map.as_deref()),
+            // Interface sounds come out of both speakers alike
+            _ => (1.0, 0.5),
+        };
+        if audibility <= 0.0 {
+            continue;
+        }
+        let channel = channels.next();
+        audio.set_volume_in_channel(volume * audibility, channel);
+        audio.set_panning_in_channel(panning, channel);
+        audio.play_in_channel(file.source.clone(), channel);
+    }
+}
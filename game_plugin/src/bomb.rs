@@ -0,0 +1,337 @@
+use crate::combat::{DamageEvent, DamageSource, Dead, DeathEvent};
+use crate::consts::*;
+use crate::map::Map;
+use crate::options::GameRng;
+use crate::player::{Player, PlayerInput};
+use crate::round::{ObjectiveEvent, Round, RoundEndReason, RoundPhase, RoundStartEvent};
+use crate::team::Team;
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+use rand::seq::IteratorRandom;
+
+pub struct BombPlugin;
+
+// One terrorist carries the bomb each round. It can be planted on a bomb site
+// and defused by the CTs, both by holding the use key until the action completes.
+impl Plugin for BombPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            gameplay_update(Role::Authority)
+                .with_system(setup_bomb_sites.system())
+                .with_system(give_bomb.system())
+                .with_system(drop_bomb.system())
+                .with_system(pick_up_bomb.system())
+                .with_system(plant_bomb.system())
+                .with_system(defuse_bomb.system())
+                .with_system(tick_bomb.system()),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_bombs.system()));
+    }
+}
+
+pub struct BombSite {
+    pub name: char,
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl BombSite {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y
+    }
+}
+
+pub struct BombSites(pub Vec<BombSite>);
+
+impl BombSites {
+    pub fn site_at(&self, pos: Vec2) -> Option<&BombSite> {
+        self.0.iter().find(|site| site.contains(pos))
+    }
+}
+
+pub struct BombCarrier;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BombState {
+    Dropped,
+    Planted { site: char },
+}
+
+pub struct Bomb {
+    pub state: BombState,
+    pub timer: Timer,
+    pub planter: Option<Entity>,
+}
+
+pub struct Planting(pub Timer);
+pub struct Defusing(pub Timer);
+
+// A site covers its Special tiles and the space above them a player can stand in
+fn setup_bomb_sites(mut commands: Commands, map: Option<Res<Map>>, sites: Option<Res<BombSites>>) {
+    let map = match map {
+        Some(map) if sites.is_none() => map,
+        _ => return,
+    };
+
+    let sites = map
+        .bomb_sites()
+        .iter()
+        .zip("ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars())
+        .map(|(tiles, name)| {
+            let mut min = Vec2::splat(f32::MAX);
+            let mut max = Vec2::splat(f32::MIN);
+            for tile in tiles {
+                let center = map.coordinate_to_pixel(tile, ARENA_W, ARENA_H);
+                min = min.min(center - 0.5 * map.tile_size);
+                max = max.max(center + 0.5 * map.tile_size);
+            }
+            max.y += PLAYER_TILE_SIZE;
+            BombSite { name, min, max }
+        })
+        .collect();
+
+    commands.insert_resource(BombSites(sites));
+}
+
+fn give_bomb(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    mut round_start: EventReader<RoundStartEvent>,
+    bombs: Query<Entity, With<Bomb>>,
+    carriers: Query<Entity, With<BombCarrier>>,
+    players: Query<(Entity, &Team), With<Player>>,
+) {
+    if round_start.iter().last().is_none() {
+        return;
+    }
+    for entity in bombs.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in carriers.iter() {
+        commands
+            .entity(entity)
+            .remove::<BombCarrier>()
+            .remove::<Planting>()
+            .remove::<Defusing>();
+    }
+
+    let carrier = players
+        .iter()
+        .filter(|(_, team)| **team == Team::Terrorists)
+        .choose(&mut rng.0);
+    if let Some((entity, _)) = carrier {
+        commands.entity(entity).insert(BombCarrier);
+    }
+}
+
+fn drop_bomb(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut deaths: EventReader<DeathEvent>,
+    carriers: Query<&Transform, With<BombCarrier>>,
+) {
+    for death in deaths.iter() {
+        if let Ok(transform) = carriers.get(death.entity) {
+            commands
+                .entity(death.entity)
+                .remove::<BombCarrier>()
+                .remove::<Planting>();
+            spawn_bomb(
+                &mut commands,
+                &mut materials,
+                transform.translation,
+                BombState::Dropped,
+                None,
+            );
+        }
+    }
+}
+
+fn pick_up_bomb(
+    mut commands: Commands,
+    bombs: Query<(Entity, &Bomb, &Transform)>,
+    players: Query<(Entity, &Team, &Transform), (With<Player>, Without<Dead>)>,
+) {
+    for (bomb_entity, bomb, bomb_transform) in bombs.iter() {
+        if bomb.state != BombState::Dropped {
+            continue;
+        }
+        let picked_up_by = players.iter().find(|(_, team, transform)| {
+            **team == Team::Terrorists
+                && transform.translation.distance(bomb_transform.translation) < BOMB_REACH
+        });
+        if let Some((player, _, _)) = picked_up_by {
+            commands.entity(bomb_entity).despawn();
+            commands.entity(player).insert(BombCarrier);
+        }
+    }
+}
+
+fn plant_bomb(
+    mut commands: Commands,
+    time: Res<Time>,
+    sites: Option<Res<BombSites>>,
+    mut round: ResMut<Round>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut carriers: Query<
+        (Entity, &Transform, &PlayerInput, Option<&mut Planting>),
+        (With<BombCarrier>, Without<Dead>),
+    >,
+) {
+    let sites = match sites {
+        Some(sites) => sites,
+        None => return,
+    };
+    for (entity, transform, input, planting) in carriers.iter_mut() {
+        let site = sites.site_at(transform.translation.truncate());
+        if !input.use_held || site.is_none() || round.phase != RoundPhase::Live {
+            if planting.is_some() {
+                commands.entity(entity).remove::<Planting>();
+            }
+            continue;
+        }
+
+        let mut planting = match planting {
+            Some(planting) => planting,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(Planting(Timer::from_seconds(PLANT_TIME, false)));
+                continue;
+            }
+        };
+        if !planting.0.tick(time.delta()).finished() {
+            continue;
+        }
+
+        commands
+            .entity(entity)
+            .remove::<Planting>()
+            .remove::<BombCarrier>();
+        spawn_bomb(
+            &mut commands,
+            &mut materials,
+            transform.translation,
+            BombState::Planted {
+                site: site.unwrap().name,
+            },
+            Some(entity),
+        );
+        round.bomb_planted = true;
+    }
+}
+
+fn defuse_bomb(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut objectives: EventWriter<ObjectiveEvent>,
+    bombs: Query<(Entity, &Bomb, &Transform)>,
+    mut defusers: Query<
+        (
+            Entity,
+            &Team,
+            &Transform,
+            &PlayerInput,
+            Option<&mut Defusing>,
+        ),
+        (With<Player>, Without<Dead>),
+    >,
+) {
+    let planted = bombs
+        .iter()
+        .find(|(_, bomb, _)| matches!(bomb.state, BombState::Planted { .. }));
+
+    for (entity, team, transform, input, defusing) in defusers.iter_mut() {
+        let bomb = planted.filter(|(_, _, bomb_transform)| {
+            *team == Team::CounterTerrorists
+                && transform.translation.distance(bomb_transform.translation) < BOMB_REACH
+        });
+        let bomb_entity = match bomb {
+            Some((bomb_entity, _, _)) if input.use_held => bomb_entity,
+            _ => {
+                if defusing.is_some() {
+                    commands.entity(entity).remove::<Defusing>();
+                }
+                continue;
+            }
+        };
+
+        let mut defusing = match defusing {
+            Some(defusing) => defusing,
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(Defusing(Timer::from_seconds(DEFUSE_TIME, false)));
+                continue;
+            }
+        };
+        if defusing.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Defusing>();
+            commands.entity(bomb_entity).despawn();
+            objectives.send(ObjectiveEvent {
+                winner: Team::CounterTerrorists,
+                reason: RoundEndReason::BombDefused,
+            });
+        }
+    }
+}
+
+fn tick_bomb(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut objectives: EventWriter<ObjectiveEvent>,
+    mut damage: EventWriter<DamageEvent>,
+    mut bombs: Query<(Entity, &mut Bomb, &Transform)>,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Dead>)>,
+) {
+    for (entity, mut bomb, bomb_transform) in bombs.iter_mut() {
+        if bomb.state == BombState::Dropped || !bomb.timer.tick(time.delta()).finished() {
+            continue;
+        }
+
+        // Damage falls off linearly towards the edge of the explosion radius
+        for (player, transform) in players.iter() {
+            let distance = transform.translation.distance(bomb_transform.translation);
+            if distance < BOMB_RADIUS {
+                damage.send(DamageEvent {
+                    target: player,
+                    amount: BOMB_DAMAGE * (1.0 - distance / BOMB_RADIUS),
+                    attacker: bomb.planter,
+                    source: DamageSource::Bomb,
+                });
+            }
+        }
+        commands.entity(entity).despawn();
+        objectives.send(ObjectiveEvent {
+            winner: Team::Terrorists,
+            reason: RoundEndReason::BombExploded,
+        });
+    }
+}
+
+fn spawn_bomb(
+    commands: &mut Commands,
+    materials: &mut Assets<ColorMaterial>,
+    position: Vec3,
+    state: BombState,
+    planter: Option<Entity>,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(Color::rgb(0.8, 0.1, 0.1).into()),
+            sprite: Sprite::new(Vec2::new(12.0, 8.0)),
+            transform: Transform::from_translation(position),
+            ..Default::default()
+        })
+        .insert(Bomb {
+            state,
+            timer: Timer::from_seconds(BOMB_TIMER, false),
+            planter,
+        });
+}
+
+fn remove_bombs(mut commands: Commands, bombs: Query<Entity, With<Bomb>>) {
+    for entity in bombs.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,191 @@
+use crate::audio::{audibility, SoundEvent};
+use crate::combat::Dead;
+use crate::consts::{
+    BOT_HEARING_THRESHOLD, BOT_INVESTIGATE_TIME, BOT_SIGHT_RANGE, BOT_WANDER_TIME, PLAYER_TILE_SIZE,
+};
+use crate::economy::{buy_time_active, BuyEvent, BuyZones, Inventory, ItemKind, Wallet};
+use crate::grenades::{can_see, Smoke};
+use crate::loading::{DataAssets, ShopAsset};
+use crate::map::Map;
+use crate::options::{GameOptions, GameRng};
+use crate::player::{spawn_player, Player, PlayerInput};
+use crate::round::{MatchConfig, Round};
+use crate::scoreboard::PlayerName;
+use crate::team::Team;
+use crate::{gameplay_enter, gameplay_update, Role};
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct BotsPlugin;
+
+// Bots are players whose PlayerInput is written here instead of coming from a keyboard
+// or the network. They wander around, shoot the closest enemy they can see, walk towards
+// enemies they hear and buy the best weapon they can afford.
+impl Plugin for BotsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(gameplay_enter(Role::Authority).with_system(spawn_bots.system()))
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(hear_sounds.system())
+                    .with_system(drive_bots.system())
+                    .with_system(buy_weapons.system()),
+            );
+    }
+}
+
+pub struct Bot {
+    wander: Timer,
+    direction: Option<Vec2>,
+    // Where the bot last heard an enemy, it goes there until it arrives or gives up
+    heard: Option<Vec2>,
+    investigate: Timer,
+}
+
+fn spawn_bots(mut commands: Commands, options: Res<GameOptions>) {
+    for number in 1..=options.bots {
+        let bot = spawn_player(&mut commands, Vec2::ZERO);
+        commands
+            .entity(bot)
+            .insert(PlayerName(format!("Bot {}", number)))
+            .insert(Bot {
+                wander: Timer::from_seconds(BOT_WANDER_TIME, true),
+                direction: None,
+                heard: None,
+                investigate: Timer::from_seconds(BOT_INVESTIGATE_TIME, false),
+            });
+    }
+}
+
+fn drive_bots(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    mut rng: ResMut<GameRng>,
+    smokes: Query<(&Smoke, &Transform)>,
+    mut bots: Query<(Entity, &Team, &Transform, &mut PlayerInput, &mut Bot), Without<Dead>>,
+    players: Query<(Entity, &Team, &Transform), (With<Player>, Without<Dead>)>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+
+    for (entity, team, transform, mut input, mut bot) in bots.iter_mut() {
+        let position = transform.translation.truncate();
+        let enemy = players
+            .iter()
+            .filter(|(other, other_team, _)| *other != entity && *other_team != team)
+            .map(|(_, _, other)| other.translation.truncate())
+            .filter(|other| other.distance(position) <= BOT_SIGHT_RANGE)
+            .filter(|other| can_see(&map, smokes.iter(), position, *other))
+            .min_by(|a, b| {
+                a.distance(position)
+                    .partial_cmp(&b.distance(position))
+                    .unwrap()
+            });
+
+        if let Some(enemy) = enemy {
+            input.aim = Some(enemy);
+            input.fire = true;
+            input.movement = None;
+            continue;
+        }
+
+        input.fire = false;
+        if let Some(heard) = bot.heard {
+            let offset = heard - position;
+            let given_up = bot.investigate.tick(time.delta()).finished();
+            if given_up || offset.length() <= PLAYER_TILE_SIZE {
+                bot.heard = None;
+            } else {
+                let y = if offset.y > PLAYER_TILE_SIZE {
+                    1.0
+                } else {
+                    0.0
+                };
+                input.movement = Some(Vec2::new(offset.x.signum(), y).normalize());
+                input.aim = Some(heard);
+                continue;
+            }
+        }
+        if bot.wander.tick(time.delta()).just_finished() || bot.direction.is_none() {
+            let x = rng.0.gen_range(-1..=1) as f32;
+            let y = if rng.0.gen_bool(0.3) { 1.0 } else { 0.0 };
+            bot.direction = Some(Vec2::new(x, y));
+        }
+        input.movement = bot
+            .direction
+            .filter(|direction| *direction != Vec2::ZERO)
+            .map(|direction| direction.normalize());
+        input.aim = input.movement.map(|direction| position + direction);
+    }
+}
+
+// Bots hear enemy sounds like a player standing where they are would
+fn hear_sounds(
+    map: Option<Res<Map>>,
+    mut events: EventReader<SoundEvent>,
+    teams: Query<&Team>,
+    mut bots: Query<(&Team, &Transform, &mut Bot), Without<Dead>>,
+) {
+    for event in events.iter() {
+        let (position, source) = match (event.position, event.source) {
+            (Some(position), Some(source)) => (position, source),
+            _ => continue,
+        };
+        let source_team = match teams.get(source) {
+            Ok(team) => *team,
+            Err(_) => continue,
+        };
+        for (team, transform, mut bot) in bots.iter_mut() {
+            if *team == source_team {
+                continue;
+            }
+            let listener = transform.translation.truncate();
+            let heard = event.loudness * audibility(position, listener, map.as_deref());
+            if heard >= BOT_HEARING_THRESHOLD {
+                bot.heard = Some(position);
+                bot.investigate.reset();
+            }
+        }
+    }
+}
+
+fn buy_weapons(
+    data: Res<DataAssets>,
+    shops: Res<Assets<ShopAsset>>,
+    zones: Option<Res<BuyZones>>,
+    round: Res<Round>,
+    config: Res<MatchConfig>,
+    mut buy_events: EventWriter<BuyEvent>,
+    bots: Query<(Entity, &Team, &Transform, &Wallet, &Inventory), (With<Bot>, Without<Dead>)>,
+) {
+    let (zones, shop) = match (zones, shops.get(data.shop.clone())) {
+        (Some(zones), Some(shop)) => (zones, shop),
+        _ => return,
+    };
+    if !buy_time_active(&round, &config) {
+        return;
+    }
+
+    for (entity, team, transform, wallet, inventory) in bots.iter() {
+        if inventory.weapon.is_some() || !zones.can_buy(*team, transform.translation.truncate()) {
+            continue;
+        }
+        let best = shop
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| {
+                item.kind == ItemKind::Weapon
+                    && item.available_for(*team)
+                    && item.price <= wallet.money
+            })
+            .max_by_key(|(_, item)| item.price);
+        if let Some((item, _)) = best {
+            buy_events.send(BuyEvent {
+                player: entity,
+                item,
+            });
+        }
+    }
+}
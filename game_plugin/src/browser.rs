@@ -0,0 +1,258 @@
+use crate::consts::{DEFAULT_PORT, DISCOVERY_INTERVAL, DISCOVERY_PORTS, DISCOVERY_TIMEOUT};
+use crate::loading::FontAssets;
+use crate::net::{
+    decode, encode, Client, ClientMessage, ServerInfo, ServerMessage, Transport, UdpTransport,
+};
+use crate::settings::Settings;
+use crate::ui::{text_bundle, ButtonMaterials};
+use crate::GameState;
+use bevy::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr};
+
+pub struct BrowserPlugin;
+
+// Lists the servers on the local network. Discovery queries are broadcast to the
+// game ports, every server that answers shows up and joining it starts the game.
+impl Plugin for BrowserPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Browser)
+                .with_system(start_discovery.system())
+                .with_system(spawn_browser.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Browser)
+                .with_system(discover_servers.system())
+                .with_system(update_server_list.system())
+                .with_system(join_server.system()),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Browser).with_system(close_browser.system()));
+    }
+}
+
+pub struct LanServer {
+    pub addr: SocketAddr,
+    pub info: ServerInfo,
+    // Round trip in milliseconds
+    pub ping: u32,
+    pub last_seen: f64,
+}
+
+pub struct LanBrowser {
+    transport: UdpTransport,
+    timer: Timer,
+    pub servers: Vec<LanServer>,
+    changed: bool,
+}
+
+impl LanBrowser {
+    pub fn new(transport: UdpTransport) -> Self {
+        Self {
+            transport,
+            timer: Timer::from_seconds(DISCOVERY_INTERVAL, true),
+            servers: Vec::new(),
+            changed: true,
+        }
+    }
+
+    pub fn query(&mut self, to: SocketAddr, now: f64) {
+        let query = encode(&ClientMessage::Discover { sent: now });
+        self.transport.send(to, &query);
+    }
+
+    // Asks every game port on the local network and on this machine
+    fn query_lan(&mut self, now: f64) {
+        for port in DEFAULT_PORT..DEFAULT_PORT + DISCOVERY_PORTS {
+            self.query((Ipv4Addr::BROADCAST, port).into(), now);
+            // Broadcasts do not always reach servers on this machine
+            self.query((Ipv4Addr::LOCALHOST, port).into(), now);
+        }
+    }
+
+    // Lists every server that answered, the ones that stopped answering are dropped
+    pub fn receive_answers(&mut self, now: f64) {
+        while let Some((addr, bytes)) = self.transport.receive() {
+            let info = match decode::<ServerMessage>(&bytes) {
+                Some(ServerMessage::Info(info)) => info,
+                _ => continue,
+            };
+            let ping = ((now - info.sent) * 1000.0).round() as u32;
+            // A local server answers both queries, it is only listed once
+            let known = self.servers.iter().position(|server| {
+                server.addr == addr
+                    || (server.addr.port() == addr.port()
+                        && (server.addr.ip().is_loopback() || addr.ip().is_loopback()))
+            });
+            let server = LanServer {
+                addr,
+                info,
+                ping,
+                last_seen: now,
+            };
+            match known {
+                Some(index) => self.servers[index] = server,
+                None => self.servers.push(server),
+            }
+            self.changed = true;
+        }
+
+        let count = self.servers.len();
+        self.servers
+            .retain(|server| now - server.last_seen < DISCOVERY_TIMEOUT);
+        if self.servers.len() != count {
+            self.changed = true;
+        }
+    }
+}
+
+struct BrowserUi;
+struct ServerList;
+struct JoinButton(SocketAddr);
+
+fn start_discovery(mut commands: Commands, time: Res<Time>) {
+    let transport = UdpTransport::bind((Ipv4Addr::UNSPECIFIED, 0).into())
+        .expect("Failed to bind discovery socket");
+    if let Err(error) = transport.enable_broadcast() {
+        warn!(
+            "LAN broadcast unavailable, only local servers are found: {}",
+            error
+        );
+    }
+    let mut browser = LanBrowser::new(transport);
+    browser.query_lan(time.seconds_since_startup());
+    commands.insert_resource(browser);
+}
+
+fn spawn_browser(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(40.0)),
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(BrowserUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("LAN servers", fonts.fira_sans.clone(), 40.0));
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        margin: Rect::all(Val::Px(20.0)),
+                        padding: Rect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    material: button_materials.panel.clone(),
+                    ..Default::default()
+                })
+                .insert(ServerList);
+        });
+}
+
+fn discover_servers(time: Res<Time>, mut browser: ResMut<LanBrowser>) {
+    let now = time.seconds_since_startup();
+    if browser.timer.tick(time.delta()).just_finished() {
+        browser.query_lan(now);
+    }
+    browser.receive_answers(now);
+}
+
+fn update_server_list(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    mut browser: ResMut<LanBrowser>,
+    lists: Query<(Entity, Option<&Children>), With<ServerList>>,
+) {
+    if !browser.changed {
+        return;
+    }
+    browser.changed = false;
+    let (list, children) = match lists.single() {
+        Ok(list) => list,
+        Err(_) => return,
+    };
+    for child in children.iter().flat_map(|children| children.iter()) {
+        commands.entity(*child).despawn_recursive();
+    }
+
+    commands.entity(list).with_children(|parent| {
+        if browser.servers.is_empty() {
+            parent.spawn_bundle(text_bundle(
+                "Searching for servers...",
+                fonts.fira_sans.clone(),
+                20.0,
+            ));
+        }
+        for server in browser.servers.iter() {
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(560.0), Val::Px(32.0)),
+                        margin: Rect::all(Val::Px(2.0)),
+                        align_items: AlignItems::Center,
+                        padding: Rect {
+                            left: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    material: button_materials.normal.clone(),
+                    ..Default::default()
+                })
+                .insert(JoinButton(server.addr))
+                .with_children(|button| {
+                    button.spawn_bundle(text_bundle(
+                        &format!(
+                            "{}  {}  {}/{}  {} ms",
+                            server.info.name,
+                            server.info.map,
+                            server.info.players,
+                            server.info.max_players,
+                            server.ping
+                        ),
+                        fonts.fira_sans.clone(),
+                        20.0,
+                    ));
+                });
+        }
+    });
+}
+
+fn join_server(
+    mut commands: Commands,
+    mut state: ResMut<State<GameState>>,
+    settings: Res<Settings>,
+    buttons: Query<(&Interaction, &JoinButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let transport = UdpTransport::bind((Ipv4Addr::UNSPECIFIED, 0).into())
+            .expect("Failed to bind client socket");
+        commands.insert_resource(Client::new(
+            Box::new(transport),
+            button.0,
+            settings.name.clone(),
+        ));
+        state.set(GameState::Playing).unwrap();
+        return;
+    }
+}
+
+fn close_browser(mut commands: Commands, ui: Query<Entity, With<BrowserUi>>) {
+    commands.remove_resource::<LanBrowser>();
+    for entity in ui.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
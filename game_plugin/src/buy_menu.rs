@@ -0,0 +1,215 @@
+use crate::actions::Actions;
+use crate::economy::{buy_time_active, BuyEvent, BuyZones};
+use crate::loading::{DataAssets, FontAssets, ShopAsset};
+use crate::player::LocalPlayer;
+use crate::round::{MatchConfig, Round};
+use crate::team::Team;
+use crate::ui::{text_bundle, ButtonMaterials};
+use crate::{playing_update, GameState, Role};
+use bevy::prelude::*;
+
+pub struct BuyMenuPlugin;
+
+// The buy menu lists every shop item the local player's team can buy.
+// Items are bought by clicking them or pressing the number in front of them.
+impl Plugin for BuyMenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            playing_update(Role::Authority)
+                .with_system(toggle_buy_menu.system())
+                .with_system(close_buy_menu.system())
+                .with_system(click_buy_button.system())
+                .with_system(press_buy_key.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(despawn_buy_menu.system()),
+        );
+    }
+}
+
+pub struct BuyMenu {
+    pub items: Vec<usize>,
+}
+
+pub struct BuyButton(pub usize);
+
+const ITEM_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+fn can_buy(
+    zones: &Option<Res<BuyZones>>,
+    round: &Round,
+    config: &MatchConfig,
+    team: Team,
+    transform: &Transform,
+) -> bool {
+    let in_zone = zones.as_ref().map_or(false, |zones| {
+        zones.can_buy(team, transform.translation.truncate())
+    });
+    in_zone && buy_time_active(round, config)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn toggle_buy_menu(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    fonts: Res<FontAssets>,
+    data: Res<DataAssets>,
+    shops: Res<Assets<ShopAsset>>,
+    button_materials: Res<ButtonMaterials>,
+    zones: Option<Res<BuyZones>>,
+    round: Res<Round>,
+    config: Res<MatchConfig>,
+    menus: Query<Entity, With<BuyMenu>>,
+    player: Query<(&Team, &Transform), With<LocalPlayer>>,
+) {
+    if !actions.toggle_buy_menu {
+        return;
+    }
+    if let Some(menu) = menus.iter().next() {
+        commands.entity(menu).despawn_recursive();
+        return;
+    }
+    let (team, transform) = match player.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if !can_buy(&zones, &round, &config, *team, transform) {
+        return;
+    }
+
+    let shop = match shops.get(data.shop.clone()) {
+        Some(shop) => shop,
+        None => return,
+    };
+    let items: Vec<usize> = shop
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.available_for(*team))
+        .map(|(index, _)| index)
+        .take(ITEM_KEYS.len())
+        .collect();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(20.0),
+                    top: Val::Px(120.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Buy menu", fonts.fira_sans.clone(), 30.0));
+            for (key, index) in items.iter().enumerate() {
+                let item = &shop.items[*index];
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(260.0), Val::Px(32.0)),
+                            margin: Rect::all(Val::Px(2.0)),
+                            align_items: AlignItems::Center,
+                            padding: Rect {
+                                left: Val::Px(8.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .insert(BuyButton(*index))
+                    .with_children(|button| {
+                        button.spawn_bundle(text_bundle(
+                            &format!("{}  {}  ${}", key + 1, item.name, item.price),
+                            fonts.fira_sans.clone(),
+                            20.0,
+                        ));
+                    });
+            }
+        })
+        .insert(BuyMenu { items });
+}
+
+// The menu closes by itself once the player leaves the buy zone or buy time is over
+fn close_buy_menu(
+    mut commands: Commands,
+    zones: Option<Res<BuyZones>>,
+    round: Res<Round>,
+    config: Res<MatchConfig>,
+    menus: Query<Entity, With<BuyMenu>>,
+    player: Query<(&Team, &Transform), With<LocalPlayer>>,
+) {
+    let allowed = match player.single() {
+        Ok((team, transform)) => can_buy(&zones, &round, &config, *team, transform),
+        Err(_) => false,
+    };
+    if allowed {
+        return;
+    }
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+fn click_buy_button(
+    mut buy_events: EventWriter<BuyEvent>,
+    buttons: Query<(&Interaction, &BuyButton), Changed<Interaction>>,
+    player: Query<Entity, With<LocalPlayer>>,
+) {
+    let player = match player.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for (interaction, button) in buttons.iter() {
+        if *interaction == Interaction::Clicked {
+            buy_events.send(BuyEvent {
+                player,
+                item: button.0,
+            });
+        }
+    }
+}
+
+fn press_buy_key(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut buy_events: EventWriter<BuyEvent>,
+    menus: Query<&BuyMenu>,
+    player: Query<Entity, With<LocalPlayer>>,
+) {
+    let (menu, player) = match (menus.single(), player.single()) {
+        (Ok(menu), Ok(player)) => (menu, player),
+        _ => return,
+    };
+    for (key, item) in ITEM_KEYS.iter().zip(menu.items.iter()) {
+        if keyboard_input.just_pressed(*key) {
+            buy_events.send(BuyEvent {
+                player,
+                item: *item,
+            });
+        }
+    }
+}
+
+fn despawn_buy_menu(mut commands: Commands, menus: Query<Entity, With<BuyMenu>>) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
@@ -0,0 +1,152 @@
+use crate::actions::Actions;
+use crate::consts::{
+    CAMERA_DEADZONE, CAMERA_LOOK_AHEAD, CAMERA_MAX_LOOK_AHEAD, CAMERA_MAX_ZOOM, CAMERA_MIN_ZOOM,
+    CAMERA_SMOOTHING, CAMERA_ZOOM, CAMERA_ZOOM_STEP,
+};
+use crate::map::Map;
+use crate::player::LocalPlayer;
+use crate::settings::Settings;
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+
+pub struct CameraPlugin;
+
+// The camera follows the local player. The player moves freely within a deadzone around
+// the camera's focus, the camera glides after the focus and never shows what lies beyond
+// the map. The mouse wheel zooms, the view can look ahead towards the aim.
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(spawn_camera.system()),
+        )
+        .add_system_set(
+            gameplay_update(Role::Everyone)
+                .with_system(zoom_camera.system().before("follow"))
+                .with_system(follow_player.system().label("follow")),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(despawn_camera.system()),
+        );
+    }
+}
+
+pub struct MainCamera;
+
+struct CameraController {
+    // Where the camera is heading
+    focus: Vec2,
+    zoom: f32,
+}
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.transform.scale = Vec3::new(CAMERA_ZOOM, CAMERA_ZOOM, 1.0);
+    commands
+        .spawn_bundle(camera)
+        .insert(MainCamera)
+        .insert(CameraController {
+            focus: Vec2::ZERO,
+            zoom: CAMERA_ZOOM,
+        });
+}
+
+// Zooming scales the camera transform, so turning the cursor into world coordinates
+// keeps working without knowing about it
+fn zoom_camera(
+    actions: Res<Actions>,
+    mut cameras: Query<(&mut Transform, &mut CameraController), With<MainCamera>>,
+) {
+    if actions.zoom == 0.0 {
+        return;
+    }
+    for (mut transform, mut controller) in cameras.iter_mut() {
+        let zoom = controller.zoom * CAMERA_ZOOM_STEP.powf(-actions.zoom);
+        controller.zoom = zoom.clamp(CAMERA_MIN_ZOOM, CAMERA_MAX_ZOOM);
+        transform.scale = Vec3::new(controller.zoom, controller.zoom, 1.0);
+    }
+}
+
+// Moves `focus` just far enough for `target` to be inside the deadzone around it
+fn drag_focus(focus: Vec2, target: Vec2) -> Vec2 {
+    let excess = |offset: f32| {
+        if offset > CAMERA_DEADZONE {
+            offset - CAMERA_DEADZONE
+        } else if offset < -CAMERA_DEADZONE {
+            offset + CAMERA_DEADZONE
+        } else {
+            0.0
+        }
+    };
+    let offset = target - focus;
+    focus + Vec2::new(excess(offset.x), excess(offset.y))
+}
+
+// Keeps a view of `half_view` around `center` inside the map,
+// a map smaller than the view is centered in it
+fn clamp_to_map(center: Vec2, half_view: Vec2, map: &Map) -> Vec2 {
+    let (min, max) = map.bounds();
+    let clamp = |value: f32, min: f32, max: f32, half: f32| {
+        if max - min <= 2.0 * half {
+            0.5 * (min + max)
+        } else {
+            value.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        clamp(center.x, min.x, max.x, half_view.x),
+        clamp(center.y, min.y, max.y, half_view.y),
+    )
+}
+
+// Without a local player, e.g. on a client still connecting, the camera stays where it is.
+// The player's own position is used while dead as well.
+fn follow_player(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    actions: Res<Actions>,
+    windows: Res<Windows>,
+    map: Option<Res<Map>>,
+    players: Query<&Transform, (With<LocalPlayer>, Without<MainCamera>)>,
+    mut cameras: Query<(&mut Transform, &mut CameraController), With<MainCamera>>,
+) {
+    let position = match players.single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    let mut target = position;
+    if let (true, Some(aim)) = (settings.camera.look_ahead, actions.aim) {
+        let look_ahead = (aim - position) * CAMERA_LOOK_AHEAD;
+        target += if look_ahead.length() > CAMERA_MAX_LOOK_AHEAD {
+            look_ahead.normalize() * CAMERA_MAX_LOOK_AHEAD
+        } else {
+            look_ahead
+        };
+    }
+    let window = windows.get_primary().map_or(Vec2::ZERO, |window| {
+        Vec2::new(window.width(), window.height())
+    });
+
+    for (mut transform, mut controller) in cameras.iter_mut() {
+        let half_view = 0.5 * window * controller.zoom;
+        let mut focus = drag_focus(controller.focus, target);
+        if let Some(map) = map.as_ref() {
+            focus = clamp_to_map(focus, half_view, map);
+        }
+        controller.focus = focus;
+
+        let current = transform.translation.truncate();
+        let share = 1.0 - (-CAMERA_SMOOTHING * time.delta_seconds()).exp();
+        let mut center = current.lerp(focus, share);
+        if let Some(map) = map.as_ref() {
+            center = clamp_to_map(center, half_view, map);
+        }
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
+
+fn despawn_camera(mut commands: Commands, cameras: Query<Entity, With<MainCamera>>) {
+    for entity in cameras.iter() {
+        commands.entity(entity).despawn();
+    }
+}
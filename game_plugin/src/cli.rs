@@ -0,0 +1,249 @@
+use crate::consts::DEFAULT_PORT;
+use crate::net::{NetworkConditions, NetworkMode};
+use crate::options::GameOptions;
+use crate::round::MatchConfig;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::FileAssetIo;
+use std::net::SocketAddr;
+
+pub const USAGE: &str = "Options:
+    --map <name>            map from assets/textures/maps (default map_one)
+    --windowed              run in a window
+    --fullscreen            run fullscreen
+    --resolution <WxH>      window size, e.g. 1280x720
+    --host                  host a match others can join
+    --port <port>           port to host on (default 27015)
+    --connect <addr>        join the server at addr, e.g. 192.168.0.2:27015
+    --browse                pick a server on the local network
+    --bots <n>              add n bots to the match
+    --seed <n>              seed for all random gameplay decisions
+    --rounds <n>            rounds a team needs to win the match (default 16)
+    --friendly-fire         let players hurt their teammates
+    --latency <ms>          simulate a one way delay
+    --jitter <ms>           simulate up to this much extra delay
+    --loss <percent>        simulate packet loss
+    --help                  show this message";
+
+// Everything that can be set when launching the game or the dedicated server
+#[derive(Debug, Clone)]
+pub struct Cli {
+    pub options: GameOptions,
+    pub mode: NetworkMode,
+    pub conditions: NetworkConditions,
+    pub config: MatchConfig,
+    // Window options override the settings file for this run
+    pub fullscreen: Option<bool>,
+    pub resolution: Option<(f32, f32)>,
+    pub port: u16,
+    pub help: bool,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Self {
+            options: GameOptions::default(),
+            mode: NetworkMode::Offline,
+            conditions: NetworkConditions::default(),
+            config: MatchConfig::default(),
+            fullscreen: None,
+            resolution: None,
+            port: DEFAULT_PORT,
+            help: false,
+        }
+    }
+}
+
+fn value<T: std::str::FromStr>(
+    name: &str,
+    args: &mut impl Iterator<Item = String>,
+    expected: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} expects {}", name, expected))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} expects {}, got '{}'", name, expected, value))
+}
+
+fn resolution(text: &str) -> Option<(f32, f32)> {
+    let mut parts = text.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    match parts.next() {
+        None if width > 0.0 && height > 0.0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+impl Cli {
+    // Parses the arguments after the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        let mut host = false;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--map" => cli.options.map = value(&arg, &mut args, "a map name")?,
+                "--windowed" => cli.fullscreen = Some(false),
+                "--fullscreen" => cli.fullscreen = Some(true),
+                "--resolution" => {
+                    let text: String = value(&arg, &mut args, "a size like 1280x720")?;
+                    let resolution = resolution(&text)
+                        .ok_or_else(|| format!("--resolution expects WxH, got '{}'", text))?;
+                    cli.resolution = Some(resolution);
+                }
+                "--host" => host = true,
+                "--port" => cli.port = value(&arg, &mut args, "a port number")?,
+                "--connect" => {
+                    let server: SocketAddr =
+                        value(&arg, &mut args, "an address like 127.0.0.1:27015")?;
+                    cli.mode = NetworkMode::Client { server };
+                }
+                "--browse" => cli.mode = NetworkMode::Browse,
+                "--bots" => cli.options.bots = value(&arg, &mut args, "a number of bots")?,
+                "--seed" => cli.options.seed = Some(value(&arg, &mut args, "a number")?),
+                "--rounds" => {
+                    let rounds: u32 = value(&arg, &mut args, "a number of rounds")?;
+                    if rounds == 0 {
+                        return Err("--rounds expects at least 1 round".to_string());
+                    }
+                    cli.config.rounds_to_win = rounds;
+                }
+                "--friendly-fire" => cli.config.friendly_fire = true,
+                "--latency" => {
+                    cli.conditions.latency = value::<f32>(&arg, &mut args, "milliseconds")? / 1000.0
+                }
+                "--jitter" => {
+                    cli.conditions.jitter = value::<f32>(&arg, &mut args, "milliseconds")? / 1000.0
+                }
+                "--loss" => {
+                    cli.conditions.packet_loss =
+                        value::<f32>(&arg, &mut args, "a percentage")? / 100.0
+                }
+                "--help" | "-h" => cli.help = true,
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        if host {
+            if cli.mode != NetworkMode::Offline {
+                return Err("--host cannot be combined with --connect or --browse".to_string());
+            }
+            cli.mode = NetworkMode::Host { port: cli.port };
+        }
+        // Without a network option the player picks what to do in the main menu
+        cli.options.menu = cli.mode == NetworkMode::Offline;
+        Ok(cli)
+    }
+
+    // Parses the process arguments, prints the usage and exits if they are wrong or --help was given
+    pub fn from_env() -> Self {
+        let cli = Cli::parse(std::env::args().skip(1)).and_then(|cli| {
+            if !cli.help {
+                cli.check_map()?;
+            }
+            Ok(cli)
+        });
+        match cli {
+            Ok(cli) if cli.help => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Ok(cli) => cli,
+            Err(error) => {
+                eprintln!("{}\n\n{}", error, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // The map is only loaded once the game starts, a typo in --map should not get that far
+    #[cfg(not(target_arch = "wasm32"))]
+    fn check_map(&self) -> Result<(), String> {
+        let path = FileAssetIo::get_root_path()
+            .join("assets")
+            .join(self.options.map_path());
+        if path.is_file() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unknown map '{}', {} does not exist",
+                self.options.map,
+                path.display()
+            ))
+        }
+    }
+
+    // There is no asset folder to look into in the browser
+    #[cfg(target_arch = "wasm32")]
+    fn check_map(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        Cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_open_the_menu() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.mode, NetworkMode::Offline);
+        assert!(cli.options.menu);
+        assert!(!cli.help);
+    }
+
+    #[test]
+    fn host_uses_the_given_port() {
+        let cli = parse(&["--host", "--port", "4000", "--bots", "3", "--seed", "7"]).unwrap();
+        assert_eq!(cli.mode, NetworkMode::Host { port: 4000 });
+        assert_eq!(cli.options.bots, 3);
+        assert_eq!(cli.options.seed, Some(7));
+        assert!(!cli.options.menu);
+    }
+
+    #[test]
+    fn connect_with_simulated_conditions() {
+        let cli = parse(&[
+            "--connect",
+            "127.0.0.1:27015",
+            "--latency",
+            "100",
+            "--loss",
+            "5",
+            "--resolution",
+            "1280x720",
+        ])
+        .unwrap();
+        let server = "127.0.0.1:27015".parse().unwrap();
+        assert_eq!(cli.mode, NetworkMode::Client { server });
+        assert_eq!(cli.conditions.latency, 0.1);
+        assert_eq!(cli.conditions.packet_loss, 0.05);
+        assert_eq!(cli.resolution, Some((1280.0, 720.0)));
+    }
+
+    #[test]
+    fn wrong_arguments_are_errors() {
+        assert!(parse(&["--host", "--browse"]).is_err());
+        assert!(parse(&["--bots"]).is_err());
+        assert!(parse(&["--port", "many"]).is_err());
+        assert!(parse(&["--resolution", "1280"]).is_err());
+        assert!(parse(&["--resolution", "0x720"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["--rounds", "0"]).is_err());
+    }
+
+    #[test]
+    fn match_rules_can_be_changed() {
+        let cli = parse(&["--rounds", "1", "--friendly-fire"]).unwrap();
+        assert_eq!(cli.config.rounds_to_win, 1);
+        assert_eq!(cli.config.halftime_after(), 0);
+        assert!(cli.config.friendly_fire);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::audio::{Sound, SoundEvent};
+use crate::consts::ARMOR_ABSORPTION;
+use crate::round::MatchConfig;
+use crate::team::Team;
+use crate::{gameplay_update, Role};
+use bevy::prelude::*;
+
+pub struct CombatPlugin;
+
+// Everything that hurts a player sends a DamageEvent instead of touching Health directly,
+// so round logic, scoring and effects can all react to the resulting DeathEvent.
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_system_set(gameplay_update(Role::Authority).with_system(apply_damage.system()));
+    }
+}
+
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.current > 0.0
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.max;
+    }
+}
+
+pub struct Armor(pub f32);
+
+// Marker for players that died this round, removed again when the next round starts
+pub struct Dead;
+
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    pub attacker: Option<Entity>,
+    pub source: DamageSource,
+}
+
+// What did the damage, its name is shown in the kill feed
+#[derive(Debug, Clone, PartialEq)]
+pub enum DamageSource {
+    Weapon(String),
+    Grenade,
+    Bomb,
+}
+
+impl DamageSource {
+    pub fn name(&self) -> &str {
+        match self {
+            DamageSource::Weapon(name) => name,
+            DamageSource::Grenade => "HE Grenade",
+            DamageSource::Bomb => "C4",
+        }
+    }
+
+    // The bomb hurts everyone near it, the planter is only credited for its kills
+    fn ignores_friendly_fire(&self) -> bool {
+        matches!(self, DamageSource::Bomb)
+    }
+}
+
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+    pub weapon: Option<String>,
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+    mut sounds: EventWriter<SoundEvent>,
+    config: Res<MatchConfig>,
+    teams: Query<&Team>,
+    mut query: Query<(&mut Health, Option<&mut Armor>, &mut Visible, &Transform), Without<Dead>>,
+) {
+    for damage in damage_events.iter() {
+        if !config.friendly_fire
+            && !damage.source.ignores_friendly_fire()
+            && is_teammate(&teams, damage.attacker, damage.target)
+        {
+            continue;
+        }
+        if let Ok((mut health, armor, mut visible, transform)) = query.get_mut(damage.target) {
+            if !health.is_alive() {
+                continue;
+            }
+            let mut amount = damage.amount;
+            if let Some(mut armor) = armor {
+                let absorbed = (amount * ARMOR_ABSORPTION).min(armor.0);
+                armor.0 -= absorbed;
+                amount -= absorbed;
+            }
+            health.current = (health.current - amount).max(0.0);
+            let position = transform.translation.truncate();
+            sounds.send(SoundEvent::at(Sound::Hit, position, damage.target));
+            if !health.is_alive() {
+                sounds.send(SoundEvent::at(Sound::Death, position, damage.target));
+                visible.is_visible = false;
+                commands.entity(damage.target).insert(Dead);
+                death_events.send(DeathEvent {
+                    entity: damage.target,
+                    killer: damage.attacker,
+                    weapon: Some(damage.source.name().to_string()),
+                });
+            }
+        }
+    }
+}
+
+// Damaging yourself is never prevented by the friendly fire setting
+fn is_teammate(teams: &Query<&Team>, attacker: Option<Entity>, target: Entity) -> bool {
+    match attacker {
+        Some(attacker) if attacker != target => {
+            matches!((teams.get(attacker), teams.get(target)), (Ok(a), Ok(b)) if a == b)
+        }
+        _ => false,
+    }
+}
@@ -6,3 +6,147 @@ pub const ARENA_W: f32 = 640.0;
 pub const ARENA_H: f32 = 640.0;
 
 pub const PLAYER_TILE_SIZE: f32 = 64.0;
+pub const PLAYER_SPEED: f32 = 150.0;
+
+pub const PLANT_TIME: f32 = 3.2;
+pub const DEFUSE_TIME: f32 = 10.0;
+pub const BOMB_TIMER: f32 = 40.0;
+pub const BOMB_RADIUS: f32 = 250.0;
+pub const BOMB_DAMAGE: f32 = 500.0;
+// Distance at which the bomb can be picked up or defused
+pub const BOMB_REACH: f32 = 40.0;
+
+pub const START_MONEY: u32 = 800;
+pub const MAX_MONEY: u32 = 16000;
+pub const KILL_REWARD: u32 = 300;
+pub const WIN_REWARD: u32 = 3250;
+pub const BOMB_WIN_REWARD: u32 = 3500;
+pub const LOSS_REWARD: u32 = 1400;
+pub const LOSS_STREAK_REWARD: u32 = 500;
+pub const MAX_LOSS_REWARD: u32 = 3400;
+// Losses in a row that count, more would not raise the loss bonus any further
+pub const MAX_LOSS_STREAK: u32 = (MAX_LOSS_REWARD - LOSS_REWARD) / LOSS_STREAK_REWARD;
+pub const MAX_GRENADES: usize = 4;
+// Distance around the spawn tiles that still counts as buy zone
+pub const BUY_ZONE_MARGIN: f32 = 40.0;
+// Share of incoming damage soaked up by armor
+pub const ARMOR_ABSORPTION: f32 = 0.5;
+
+pub const GRENADE_SPEED: f32 = 450.0;
+pub const GRENADE_GRAVITY: f32 = 900.0;
+// Share of the velocity kept when a grenade bounces off a tile
+pub const GRENADE_BOUNCE: f32 = 0.45;
+pub const GRENADE_FUSE: f32 = 1.6;
+pub const HE_RADIUS: f32 = 180.0;
+pub const HE_DAMAGE: f32 = 98.0;
+pub const FLASH_RADIUS: f32 = 400.0;
+pub const FLASH_DURATION: f32 = 3.5;
+pub const SMOKE_RADIUS: f32 = 90.0;
+pub const SMOKE_DURATION: f32 = 18.0;
+
+pub const DEFAULT_PORT: u16 = 27015;
+// Snapshots per second sent by the server
+pub const SNAPSHOT_RATE: f32 = 20.0;
+// Seconds without a packet before a client is dropped
+pub const CLIENT_TIMEOUT: f64 = 5.0;
+// Seconds without a snapshot before a client gives up on the server
+pub const SERVER_TIMEOUT: f64 = 5.0;
+// Seconds between connection attempts of a client
+pub const CONNECT_RETRY: f32 = 0.5;
+
+// Length of one movement simulation step in seconds
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+// Inputs of a remote player the server keeps before dropping the oldest
+pub const MAX_QUEUED_INPUTS: usize = 8;
+// Input commands repeated in every input packet to survive packet loss
+pub const INPUT_REDUNDANCY: usize = 3;
+// Distance between predicted and server position that triggers a correction
+pub const PREDICTION_TOLERANCE: f32 = 0.5;
+// Unacknowledged inputs a client keeps for replaying
+pub const MAX_PENDING_INPUTS: usize = 128;
+// Snapshots the server keeps as baselines for delta compression, the client as well
+pub const SNAPSHOT_HISTORY: usize = 32;
+// How far in the past remote players are shown, two snapshots at the default rate
+pub const INTERPOLATION_DELAY: f64 = 0.1;
+// Seconds between two bandwidth reports in the log
+pub const BANDWIDTH_REPORT_INTERVAL: f32 = 5.0;
+
+// Hitscan
+pub const WEAPON_RANGE: f32 = 1200.0;
+// Half size of the square players are hit in
+pub const HITBOX_HALF_SIZE: f32 = 0.4 * PLAYER_TILE_SIZE;
+// Oldest view of the world the server accepts for a shot, in seconds
+pub const MAX_REWIND: f32 = 0.25;
+pub const MAX_PLAYERS: u32 = 10;
+// LAN discovery queries go to this many ports starting at DEFAULT_PORT,
+// so several servers on one machine can be found
+pub const DISCOVERY_PORTS: u16 = 8;
+// Seconds between two discovery queries of the server browser
+pub const DISCOVERY_INTERVAL: f32 = 1.0;
+// Servers that did not answer for this long disappear from the browser
+pub const DISCOVERY_TIMEOUT: f64 = 3.5;
+
+// Bots
+// Seconds a bot keeps walking in one direction
+pub const BOT_WANDER_TIME: f32 = 2.0;
+// Bots notice enemies up to this far away
+pub const BOT_SIGHT_RANGE: f32 = 500.0;
+// Sounds heard at least this loud (see audio::audibility) make bots look for their source
+pub const BOT_HEARING_THRESHOLD: f32 = 0.2;
+// Seconds a bot keeps walking towards a sound before it goes back to wandering
+pub const BOT_INVESTIGATE_TIME: f32 = 4.0;
+
+// Settings
+// Written next to where the game is started from
+pub const SETTINGS_FILE: &str = "settings.ron";
+pub const MAX_NAME_LENGTH: usize = 16;
+
+// Audio
+// Sounds further away from the listener are not heard
+pub const HEARING_RANGE: f32 = 1200.0;
+// A sound this far to the side comes only out of one speaker
+pub const PANNING_DISTANCE: f32 = 400.0;
+// Every solid tile between a sound and the listener multiplies its volume by this
+pub const OCCLUSION_PER_TILE: f32 = 0.6;
+// Sounds take turns on this many channels, each with its own volume and panning
+pub const SOUND_CHANNELS: usize = 8;
+// Loudness of the movement of a sneaking player, 1 is walking normally
+pub const SNEAK_LOUDNESS: f32 = 0.3;
+
+// Animation
+pub const MUZZLE_FLASH_SIZE: f32 = 12.0;
+// Seconds a muzzle flash stays on screen
+pub const MUZZLE_FLASH_TIME: f32 = 0.05;
+
+// Scoreboard
+pub const KILL_SCORE: u32 = 2;
+pub const ASSIST_SCORE: u32 = 1;
+// Seconds between two updates of the scoreboard, and the server sending it to clients
+pub const SCOREBOARD_INTERVAL: f32 = 0.5;
+// Kills listed in the kill feed at once, and the seconds each stays there
+pub const KILL_FEED_LENGTH: usize = 5;
+pub const KILL_FEED_TIME: f64 = 6.0;
+
+// Minimap
+// Width and height in pixels, on screen and of its texture
+pub const MINIMAP_SIZE: usize = 160;
+// World units per minimap pixel when rotating, a fixed minimap fits the whole arena
+pub const MINIMAP_ROTATING_SCALE: f32 = 2.0;
+// Seconds between two redraws
+pub const MINIMAP_INTERVAL: f32 = 0.05;
+// Half size of the player markers in minimap pixels
+pub const MINIMAP_MARKER: i32 = 2;
+
+// Camera
+// Scale of the view, below 1 zooms in. The mouse wheel changes it by CAMERA_ZOOM_STEP per line.
+pub const CAMERA_ZOOM: f32 = 0.7;
+pub const CAMERA_MIN_ZOOM: f32 = 0.4;
+pub const CAMERA_MAX_ZOOM: f32 = 1.2;
+pub const CAMERA_ZOOM_STEP: f32 = 1.1;
+// Half size of the area around the camera focus the player moves in without moving the focus
+pub const CAMERA_DEADZONE: f32 = 40.0;
+// How fast the camera catches up with its focus, the share of the way left per second is e^-x
+pub const CAMERA_SMOOTHING: f32 = 6.0;
+// Share of the way towards the aim the camera looks ahead, and the farthest it does
+pub const CAMERA_LOOK_AHEAD: f32 = 0.3;
+pub const CAMERA_MAX_LOOK_AHEAD: f32 = 120.0;
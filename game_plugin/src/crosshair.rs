@@ -0,0 +1,111 @@
+use crate::settings::Settings;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct CrosshairPlugin;
+
+// Replaces the mouse cursor with a crosshair while the match is on screen.
+// It is rebuilt when returning from the menus, so settings changes show up.
+impl Plugin for CrosshairPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(spawn_crosshair.system()),
+        )
+        .add_system_set(
+            SystemSet::on_resume(GameState::Playing).with_system(spawn_crosshair.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(follow_cursor.system()),
+        )
+        .add_system_set(
+            SystemSet::on_pause(GameState::Playing).with_system(remove_crosshair.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(remove_crosshair.system()),
+        );
+    }
+}
+
+struct Crosshair;
+
+fn line_size(settings: &Settings, vertical: bool) -> Size<Val> {
+    let length = Val::Px(settings.crosshair.size);
+    let thickness = Val::Px(settings.crosshair.thickness);
+    if vertical {
+        Size::new(thickness, length)
+    } else {
+        Size::new(length, thickness)
+    }
+}
+
+fn spawn_crosshair(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut windows: ResMut<Windows>,
+) {
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_cursor_visibility(false);
+    }
+    let size = settings.crosshair.size;
+    let material = materials.add(settings.crosshair.color().into());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(size), Val::Px(size)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(Crosshair)
+        .with_children(|parent| {
+            for vertical in [false, true].iter().copied() {
+                parent.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: line_size(&settings, vertical),
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    material: material.clone(),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+// UI positions and the cursor both start at the bottom left of the window
+fn follow_cursor(
+    settings: Res<Settings>,
+    windows: Res<Windows>,
+    mut crosshairs: Query<&mut Style, With<Crosshair>>,
+) {
+    let cursor = match windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+    {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let half_size = 0.5 * settings.crosshair.size;
+    for mut style in crosshairs.iter_mut() {
+        style.position.left = Val::Px(cursor.x - half_size);
+        style.position.bottom = Val::Px(cursor.y - half_size);
+    }
+}
+
+fn remove_crosshair(
+    mut commands: Commands,
+    mut windows: ResMut<Windows>,
+    crosshairs: Query<Entity, With<Crosshair>>,
+) {
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_cursor_visibility(true);
+    }
+    for entity in crosshairs.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
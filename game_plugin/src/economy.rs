@@ -0,0 +1,346 @@
+use crate::combat::{Armor, DeathEvent};
+use crate::consts::*;
+use crate::loading::{DataAssets, ShopAsset};
+use crate::map::Map;
+use crate::player::Player;
+use crate::round::{HalftimeEvent, MatchConfig, Round, RoundEndEvent, RoundEndReason, RoundPhase};
+use crate::team::Team;
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub struct EconomyPlugin;
+
+// Money is earned through kills and round results and spent in the team's buy zone
+// on the items listed in `assets/data/items.shop.csv`.
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<BuyEvent>()
+            .init_resource::<LossStreaks>()
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(setup_buy_zones.system())
+                    .with_system(give_wallet.system())
+                    .with_system(kill_reward.system())
+                    .with_system(round_reward.system())
+                    .with_system(reset_economy.system())
+                    .with_system(buy_items.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(reset_loss_streaks.system()),
+            );
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    HeGrenade,
+    Flashbang,
+    SmokeGrenade,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShopItem {
+    pub name: String,
+    pub kind: ItemKind,
+    pub price: u32,
+    pub side: Option<Team>,
+    pub damage: Option<f32>,
+    pub fire_rate: Option<f32>,
+    pub magazine: Option<u32>,
+    pub reserve: Option<u32>,
+    pub armor: Option<f32>,
+    // Shown in the kill feed for kills with the item
+    pub icon: Option<String>,
+}
+
+impl ShopItem {
+    pub fn available_for(&self, team: Team) -> bool {
+        self.side.map_or(true, |side| side == team)
+    }
+}
+
+pub struct Wallet {
+    pub money: u32,
+}
+
+impl Wallet {
+    pub fn add(&mut self, amount: u32) {
+        self.money = (self.money + amount).min(MAX_MONEY);
+    }
+}
+
+pub struct Weapon {
+    pub name: String,
+    pub damage: f32,
+    pub fire_rate: f32,
+    pub magazine: u32,
+    pub ammo: u32,
+    pub reserve: u32,
+    // Seconds until the weapon can fire again, after a shot or while reloading
+    pub cooldown: f32,
+    // The magazine is refilled when the reload animation says so
+    pub reloading: bool,
+}
+
+impl Weapon {
+    fn from_item(item: &ShopItem) -> Self {
+        let magazine = item.magazine.unwrap_or(0);
+        Self {
+            name: item.name.clone(),
+            damage: item.damage.unwrap_or(0.0),
+            fire_rate: item.fire_rate.unwrap_or(1.0),
+            magazine,
+            ammo: magazine,
+            reserve: item.reserve.unwrap_or(0),
+            cooldown: 0.0,
+            reloading: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Inventory {
+    pub weapon: Option<Weapon>,
+    pub grenades: Vec<ItemKind>,
+}
+
+pub struct BuyZone {
+    pub team: Team,
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl BuyZone {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        pos.x >= self.min.x && pos.x <= self.max.x && pos.y >= self.min.y && pos.y <= self.max.y
+    }
+}
+
+pub struct BuyZones(pub Vec<BuyZone>);
+
+impl BuyZones {
+    pub fn can_buy(&self, team: Team, pos: Vec2) -> bool {
+        self.0
+            .iter()
+            .any(|zone| zone.team == team && zone.contains(pos))
+    }
+}
+
+#[derive(Default)]
+pub struct LossStreaks {
+    pub terrorists: u32,
+    pub counter_terrorists: u32,
+}
+
+impl LossStreaks {
+    fn get_mut(&mut self, team: Team) -> &mut u32 {
+        match team {
+            Team::Terrorists => &mut self.terrorists,
+            Team::CounterTerrorists => &mut self.counter_terrorists,
+        }
+    }
+
+    // Counts a round and returns the loss bonus of the losing team. Like in CS a win
+    // only lowers the winner's streak by one, a single win does not reset its bonus.
+    fn record(&mut self, winner: Team) -> u32 {
+        let won = self.get_mut(winner);
+        *won = won.saturating_sub(1);
+        let streak = self.get_mut(winner.opponent());
+        let bonus = (LOSS_REWARD + LOSS_STREAK_REWARD * *streak).min(MAX_LOSS_REWARD);
+        *streak = (*streak + 1).min(MAX_LOSS_STREAK);
+        bonus
+    }
+}
+
+pub struct BuyEvent {
+    pub player: Entity,
+    pub item: usize,
+}
+
+// Buying is allowed during warmup, freeze time and the first seconds of a round
+pub fn buy_time_active(round: &Round, config: &MatchConfig) -> bool {
+    match round.phase {
+        RoundPhase::Warmup | RoundPhase::FreezeTime => true,
+        RoundPhase::Live => round.timer.elapsed_secs() < config.buy_time,
+        _ => false,
+    }
+}
+
+// The buy zone of a team is the area around its spawn tiles
+fn setup_buy_zones(mut commands: Commands, map: Option<Res<Map>>, zones: Option<Res<BuyZones>>) {
+    let map = match map {
+        Some(map) if zones.is_none() => map,
+        _ => return,
+    };
+
+    let zones = [Team::Terrorists, Team::CounterTerrorists]
+        .iter()
+        .map(|team| {
+            let mut min = Vec2::splat(f32::MAX);
+            let mut max = Vec2::splat(f32::MIN);
+            for center in map.spawn_area(*team) {
+                min = min.min(center - 0.5 * map.tile_size);
+                max = max.max(center + 0.5 * map.tile_size);
+            }
+            BuyZone {
+                team: *team,
+                min: min - Vec2::splat(BUY_ZONE_MARGIN),
+                max: max + Vec2::splat(BUY_ZONE_MARGIN),
+            }
+        })
+        .collect();
+
+    commands.insert_resource(BuyZones(zones));
+}
+
+fn give_wallet(mut commands: Commands, joined: Query<Entity, Added<Player>>) {
+    for entity in joined.iter() {
+        commands
+            .entity(entity)
+            .insert(Wallet { money: START_MONEY })
+            .insert(Inventory::default());
+    }
+}
+
+fn kill_reward(
+    mut deaths: EventReader<DeathEvent>,
+    teams: Query<&Team>,
+    mut wallets: Query<&mut Wallet>,
+) {
+    for death in deaths.iter() {
+        let killer = match death.killer {
+            Some(killer) if killer != death.entity => killer,
+            _ => continue,
+        };
+        let enemy_killed = matches!(
+            (teams.get(killer), teams.get(death.entity)),
+            (Ok(a), Ok(b)) if a != b
+        );
+        if let (true, Ok(mut wallet)) = (enemy_killed, wallets.get_mut(killer)) {
+            wallet.add(KILL_REWARD);
+        }
+    }
+}
+
+fn round_reward(
+    mut round_end: EventReader<RoundEndEvent>,
+    mut loss_streaks: ResMut<LossStreaks>,
+    mut players: Query<(&Team, &mut Wallet)>,
+) {
+    for result in round_end.iter() {
+        let win_bonus = match result.reason {
+            RoundEndReason::BombExploded | RoundEndReason::BombDefused => BOMB_WIN_REWARD,
+            _ => WIN_REWARD,
+        };
+
+        let loss_bonus = loss_streaks.record(result.winner);
+
+        for (team, mut wallet) in players.iter_mut() {
+            if *team == result.winner {
+                wallet.add(win_bonus);
+            } else {
+                wallet.add(loss_bonus);
+            }
+        }
+    }
+}
+
+// Both halves start with the pistol round economy
+fn reset_economy(
+    mut halftime: EventReader<HalftimeEvent>,
+    mut loss_streaks: ResMut<LossStreaks>,
+    mut players: Query<(&mut Wallet, &mut Inventory, &mut Armor)>,
+) {
+    if halftime.iter().last().is_none() {
+        return;
+    }
+    *loss_streaks = LossStreaks::default();
+    for (mut wallet, mut inventory, mut armor) in players.iter_mut() {
+        wallet.money = START_MONEY;
+        *inventory = Inventory::default();
+        armor.0 = 0.0;
+    }
+}
+
+fn buy_items(
+    mut buy_events: EventReader<BuyEvent>,
+    data: Res<DataAssets>,
+    shops: Res<Assets<ShopAsset>>,
+    zones: Option<Res<BuyZones>>,
+    round: Res<Round>,
+    config: Res<MatchConfig>,
+    mut players: Query<(&Team, &Transform, &mut Wallet, &mut Inventory, &mut Armor)>,
+) {
+    let shop = match shops.get(data.shop.clone()) {
+        Some(shop) => shop,
+        None => return,
+    };
+    let zones = match zones {
+        Some(zones) => zones,
+        None => return,
+    };
+
+    for buy in buy_events.iter() {
+        let (team, transform, mut wallet, mut inventory, mut armor) =
+            match players.get_mut(buy.player) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+        let item = match shop.items.get(buy.item) {
+            Some(item) => item,
+            None => continue,
+        };
+        if !item.available_for(*team)
+            || item.price > wallet.money
+            || !buy_time_active(&round, &config)
+            || !zones.can_buy(*team, transform.translation.truncate())
+        {
+            continue;
+        }
+
+        match item.kind {
+            ItemKind::Weapon => inventory.weapon = Some(Weapon::from_item(item)),
+            ItemKind::Armor => {
+                let value = item.armor.unwrap_or(0.0);
+                if armor.0 >= value {
+                    continue;
+                }
+                armor.0 = value;
+            }
+            kind => {
+                if inventory.grenades.len() >= MAX_GRENADES {
+                    continue;
+                }
+                inventory.grenades.push(kind);
+            }
+        }
+        wallet.money -= item.price;
+    }
+}
+
+fn reset_loss_streaks(mut loss_streaks: ResMut<LossStreaks>) {
+    *loss_streaks = LossStreaks::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_win_lowers_the_loss_streak_by_one() {
+        let mut streaks = LossStreaks::default();
+        let bonuses: Vec<u32> = (0..6)
+            .map(|_| streaks.record(Team::CounterTerrorists))
+            .collect();
+        assert_eq!(bonuses, vec![1400, 1900, 2400, 2900, 3400, 3400]);
+        assert_eq!(streaks.terrorists, MAX_LOSS_STREAK);
+
+        // One round won, the next loss still pays almost the maximum
+        assert_eq!(streaks.record(Team::Terrorists), 1400);
+        assert_eq!(streaks.terrorists, MAX_LOSS_STREAK - 1);
+        assert_eq!(streaks.record(Team::CounterTerrorists), 2900);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::combat::{DamageEvent, DamageSource, Dead};
+use crate::consts::*;
+use crate::economy::{Inventory, ItemKind};
+use crate::map::Map;
+use crate::player::{facing, LocalPlayer, Player, PlayerInput};
+use crate::round::RoundStartEvent;
+use crate::{gameplay_enter, gameplay_update, GameState, Role};
+use bevy::prelude::*;
+
+pub struct GrenadesPlugin;
+
+// Grenades fly in an arc, bounce off solid tiles and go off when their fuse runs out.
+// Smokes stay on the field for a while and block every visibility check done with `can_see`.
+impl Plugin for GrenadesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GrenadeMaterials>()
+            .add_system_set(
+                gameplay_enter(Role::Authority).with_system(spawn_flash_overlay.system()),
+            )
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(throw_grenade.system())
+                    .with_system(move_grenades.system())
+                    .with_system(detonate_grenades.system())
+                    .with_system(tick_smokes.system())
+                    .with_system(tick_flashed.system())
+                    .with_system(clear_grenades.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(remove_grenades.system()),
+            );
+    }
+}
+
+pub struct Grenade {
+    pub kind: ItemKind,
+    pub velocity: Vec2,
+    pub fuse: Timer,
+    pub thrower: Entity,
+}
+
+pub struct Smoke {
+    pub radius: f32,
+    pub timer: Timer,
+}
+
+pub struct Flashed(pub Timer);
+
+pub struct FlashOverlay;
+
+struct GrenadeMaterials {
+    he_grenade: Handle<ColorMaterial>,
+    flashbang: Handle<ColorMaterial>,
+    smoke_grenade: Handle<ColorMaterial>,
+    smoke: Handle<ColorMaterial>,
+    // Its alpha follows how flashed the local player is
+    overlay: Handle<ColorMaterial>,
+}
+
+impl FromWorld for GrenadeMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        GrenadeMaterials {
+            he_grenade: materials.add(Color::rgb(0.3, 0.5, 0.2).into()),
+            flashbang: materials.add(Color::rgb(0.9, 0.9, 0.9).into()),
+            smoke_grenade: materials.add(Color::rgb(0.5, 0.5, 0.5).into()),
+            smoke: materials.add(Color::rgba(0.6, 0.6, 0.6, 0.85).into()),
+            overlay: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.0).into()),
+        }
+    }
+}
+
+// Line of sight through the map that is also blocked by active smokes
+pub fn can_see<'a>(
+    map: &Map,
+    mut smokes: impl Iterator<Item = (&'a Smoke, &'a Transform)>,
+    from: Vec2,
+    to: Vec2,
+) -> bool {
+    map.line_of_sight(from, to)
+        && smokes.all(|(smoke, transform)| {
+            distance_to_segment(transform.translation.truncate(), from, to) > smoke.radius
+        })
+}
+
+fn distance_to_segment(point: Vec2, from: Vec2, to: Vec2) -> f32 {
+    let segment = to - from;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(from);
+    }
+    let t = ((point - from).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(from + t * segment)
+}
+
+fn throw_grenade(
+    mut commands: Commands,
+    materials: Res<GrenadeMaterials>,
+    mut players: Query<(Entity, &Transform, &PlayerInput, &mut Inventory), Without<Dead>>,
+) {
+    for (entity, transform, input, mut inventory) in players.iter_mut() {
+        if !input.throw_grenade || inventory.grenades.is_empty() {
+            continue;
+        }
+        let kind = inventory.grenades.remove(0);
+        let material = match kind {
+            ItemKind::HeGrenade => materials.he_grenade.clone(),
+            ItemKind::Flashbang => materials.flashbang.clone(),
+            ItemKind::SmokeGrenade => materials.smoke_grenade.clone(),
+            // Only grenades are bought into the grenade slots
+            ItemKind::Weapon | ItemKind::Armor => continue,
+        };
+        let position = transform.translation.truncate();
+        let direction = input
+            .aim
+            .map(|aim| aim - position)
+            .filter(|direction| *direction != Vec2::ZERO)
+            .map_or(Vec2::X, |direction| direction.normalize());
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                material,
+                sprite: Sprite::new(Vec2::new(6.0, 6.0)),
+                transform: Transform::from_translation(position.extend(3.0)),
+                ..Default::default()
+            })
+            .insert(Grenade {
+                kind,
+                velocity: direction * GRENADE_SPEED,
+                fuse: Timer::from_seconds(GRENADE_FUSE, false),
+                thrower: entity,
+            });
+    }
+}
+
+// Each axis is moved separately so a grenade hitting a wall only loses its horizontal speed
+fn move_grenades(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    mut grenades: Query<(&mut Grenade, &mut Transform)>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    let delta = time.delta_seconds();
+    for (mut grenade, mut transform) in grenades.iter_mut() {
+        grenade.velocity.y -= GRENADE_GRAVITY * delta;
+        let position = transform.translation.truncate();
+
+        let next_x = position + Vec2::new(grenade.velocity.x * delta, 0.0);
+        if map.is_solid_at(next_x) {
+            grenade.velocity.x *= -GRENADE_BOUNCE;
+        } else {
+            transform.translation.x = next_x.x;
+        }
+
+        let next_y = transform.translation.truncate() + Vec2::new(0.0, grenade.velocity.y * delta);
+        if map.is_solid_at(next_y) {
+            grenade.velocity.y *= -GRENADE_BOUNCE;
+            grenade.velocity.x *= GRENADE_BOUNCE;
+        } else {
+            transform.translation.y = next_y.y;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn detonate_grenades(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    materials: Res<GrenadeMaterials>,
+    mut damage: EventWriter<DamageEvent>,
+    mut grenades: Query<(Entity, &mut Grenade, &Transform)>,
+    smokes: Query<(&Smoke, &Transform)>,
+    players: Query<(Entity, &Transform, &TextureAtlasSprite), (With<Player>, Without<Dead>)>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    for (entity, mut grenade, transform) in grenades.iter_mut() {
+        if !grenade.fuse.tick(time.delta()).finished() {
+            continue;
+        }
+        commands.entity(entity).despawn();
+        let position = transform.translation.truncate();
+
+        match grenade.kind {
+            ItemKind::HeGrenade => {
+                // Smoke does not stop shrapnel, only walls do
+                for (player, player_transform, _) in players.iter() {
+                    let target = player_transform.translation.truncate();
+                    let distance = position.distance(target);
+                    if distance < HE_RADIUS && map.line_of_sight(position, target) {
+                        damage.send(DamageEvent {
+                            target: player,
+                            amount: HE_DAMAGE * (1.0 - distance / HE_RADIUS),
+                            attacker: Some(grenade.thrower),
+                            source: DamageSource::Grenade,
+                        });
+                    }
+                }
+            }
+            ItemKind::Flashbang => {
+                for (player, player_transform, sprite) in players.iter() {
+                    let target = player_transform.translation.truncate();
+                    let distance = position.distance(target);
+                    let looking_at = facing(sprite).dot(position - target) > 0.0;
+                    if distance < FLASH_RADIUS
+                        && looking_at
+                        && can_see(&map, smokes.iter(), target, position)
+                    {
+                        let duration = FLASH_DURATION * (1.0 - distance / FLASH_RADIUS);
+                        commands
+                            .entity(player)
+                            .insert(Flashed(Timer::from_seconds(duration, false)));
+                    }
+                }
+            }
+            ItemKind::SmokeGrenade => {
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        material: materials.smoke.clone(),
+                        sprite: Sprite::new(Vec2::splat(2.0 * SMOKE_RADIUS)),
+                        transform: Transform::from_translation(position.extend(4.0)),
+                        ..Default::default()
+                    })
+                    .insert(Smoke {
+                        radius: SMOKE_RADIUS,
+                        timer: Timer::from_seconds(SMOKE_DURATION, false),
+                    });
+            }
+            ItemKind::Weapon | ItemKind::Armor => {}
+        }
+    }
+}
+
+fn tick_smokes(mut commands: Commands, time: Res<Time>, mut smokes: Query<(Entity, &mut Smoke)>) {
+    for (entity, mut smoke) in smokes.iter_mut() {
+        if smoke.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn spawn_flash_overlay(mut commands: Commands, materials: Res<GrenadeMaterials>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..Default::default()
+            },
+            material: materials.overlay.clone(),
+            ..Default::default()
+        })
+        .insert(FlashOverlay);
+}
+
+// The white-out fades with the remaining flash time of the local player
+fn tick_flashed(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut flashed: Query<(Entity, &mut Flashed, Option<&LocalPlayer>)>,
+    overlays: Query<&Handle<ColorMaterial>, With<FlashOverlay>>,
+) {
+    let mut alpha = 0.0;
+    for (entity, mut flash, local) in flashed.iter_mut() {
+        if flash.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Flashed>();
+            continue;
+        }
+        if local.is_some() {
+            alpha = flash.0.percent_left().min(1.0);
+        }
+    }
+
+    for handle in overlays.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color.set_a(alpha);
+        }
+    }
+}
+
+fn clear_grenades(
+    mut commands: Commands,
+    mut round_start: EventReader<RoundStartEvent>,
+    grenades: Query<Entity, Or<(With<Grenade>, With<Smoke>)>>,
+    flashed: Query<Entity, With<Flashed>>,
+) {
+    if round_start.iter().last().is_none() {
+        return;
+    }
+    for entity in grenades.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in flashed.iter() {
+        commands.entity(entity).remove::<Flashed>();
+    }
+}
+
+fn remove_grenades(
+    mut commands: Commands,
+    grenades: Query<Entity, Or<(With<Grenade>, With<Smoke>, With<FlashOverlay>)>>,
+) {
+    for entity in grenades.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,181 @@
+use crate::combat::{Armor, Health};
+use crate::economy::{Inventory, Wallet};
+use crate::loading::FontAssets;
+use crate::player::LocalPlayer;
+use crate::round::Round;
+use crate::ui::{text_bundle, ButtonMaterials};
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+
+pub struct HudPlugin;
+
+// Health, armor, money and ammo of the local player at the bottom of the screen,
+// the round timer and the score at the top. Texts only change when what they show does.
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_hud.system()))
+            .add_system_set(
+                gameplay_update(Role::Everyone)
+                    .with_system(update_player_hud.system())
+                    .with_system(update_round_hud.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_hud.system()),
+            );
+    }
+}
+
+struct Hud;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HudText {
+    Health,
+    Armor,
+    Money,
+    Ammo,
+    Timer,
+    Score,
+}
+
+// A box at the edge of the screen, its texts stacked from the top
+fn spawn_corner(
+    parent: &mut ChildBuilder,
+    position: Rect<Val>,
+    texts: &[HudText],
+    font: &Handle<Font>,
+    button_materials: &ButtonMaterials,
+) {
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .with_children(|corner| {
+            for text in texts.iter() {
+                corner
+                    .spawn_bundle(text_bundle("", font.clone(), 28.0))
+                    .insert(*text);
+            }
+        });
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+) {
+    let font = &fonts.fira_sans;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            let bottom_left = Rect {
+                left: Val::Px(16.0),
+                bottom: Val::Px(16.0),
+                ..Default::default()
+            };
+            let bottom_right = Rect {
+                right: Val::Px(16.0),
+                bottom: Val::Px(16.0),
+                ..Default::default()
+            };
+            let top = Rect {
+                left: Val::Percent(45.0),
+                top: Val::Px(8.0),
+                ..Default::default()
+            };
+            let player = [HudText::Health, HudText::Armor];
+            let loadout = [HudText::Money, HudText::Ammo];
+            let round = [HudText::Timer, HudText::Score];
+            spawn_corner(parent, bottom_left, &player, font, &button_materials);
+            spawn_corner(parent, bottom_right, &loadout, font, &button_materials);
+            spawn_corner(parent, top, &round, font, &button_materials);
+        });
+}
+
+// Writing a text marks it changed and gets it laid out again, even with the same value
+fn set_text(text: &mut Mut<Text>, value: String) {
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+fn update_player_hud(
+    new_texts: Query<(), Added<HudText>>,
+    changed: Query<
+        (),
+        (
+            With<LocalPlayer>,
+            Or<(
+                Changed<Health>,
+                Changed<Armor>,
+                Changed<Wallet>,
+                Changed<Inventory>,
+            )>,
+        ),
+    >,
+    players: Query<(&Health, &Armor, Option<&Wallet>, Option<&Inventory>), With<LocalPlayer>>,
+    mut texts: Query<(&HudText, &mut Text)>,
+) {
+    if new_texts.iter().next().is_none() && changed.iter().next().is_none() {
+        return;
+    }
+    let (health, armor, wallet, inventory) = match players.single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let weapon = inventory.and_then(|inventory| inventory.weapon.as_ref());
+
+    for (hud_text, mut text) in texts.iter_mut() {
+        let value = match hud_text {
+            HudText::Health => format!("Health {}", health.current.ceil()),
+            HudText::Armor => format!("Armor {}", armor.0.ceil()),
+            HudText::Money => format!("$ {}", wallet.map_or(0, |wallet| wallet.money)),
+            HudText::Ammo => match weapon {
+                Some(weapon) if weapon.reloading => "Reloading".to_string(),
+                Some(weapon) => format!("{} / {}", weapon.ammo, weapon.reserve),
+                None => "-".to_string(),
+            },
+            _ => continue,
+        };
+        set_text(&mut text, value);
+    }
+}
+
+// The round timer ticks every frame, the text only changes once a second
+fn update_round_hud(round: Res<Round>, mut texts: Query<(&HudText, &mut Text)>) {
+    let seconds = round.seconds_left().ceil() as u32;
+    for (hud_text, mut text) in texts.iter_mut() {
+        let value = match hud_text {
+            HudText::Timer => format!("{}:{:02}", seconds / 60, seconds % 60),
+            HudText::Score => format!(
+                "T {} : {} CT",
+                round.terrorist_score, round.counter_terrorist_score
+            ),
+            _ => continue,
+        };
+        set_text(&mut text, value);
+    }
+}
+
+fn despawn_hud(mut commands: Commands, hud: Query<Entity, With<Hud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,19 +1,74 @@
 mod actions;
+mod animation;
+mod audio;
+mod bomb;
+mod bots;
+mod browser;
+mod buy_menu;
+mod camera;
+pub mod cli;
+mod combat;
 mod consts;
+mod crosshair;
+mod economy;
+mod grenades;
+mod hud;
 mod loading;
 mod map;
+mod menu;
+mod minimap;
+pub mod net;
+mod options;
+mod pause;
 mod player;
+mod round;
+mod scoreboard;
+mod settings;
+mod team;
+mod ui;
+mod weapons;
 
 use crate::actions::ActionsPlugin;
+use crate::animation::AnimationPlugin;
+use crate::audio::InternalAudioPlugin;
+use crate::bomb::BombPlugin;
+use crate::bots::BotsPlugin;
+use crate::browser::BrowserPlugin;
+use crate::buy_menu::BuyMenuPlugin;
+use crate::camera::CameraPlugin;
+use crate::combat::CombatPlugin;
+use crate::crosshair::CrosshairPlugin;
+use crate::economy::EconomyPlugin;
+use crate::grenades::GrenadesPlugin;
+use crate::hud::HudPlugin;
 use crate::loading::LoadingPlugin;
 use crate::map::MapPlugin;
+use crate::menu::MenuPlugin;
+use crate::minimap::MinimapPlugin;
+use crate::net::{network_mode, NetworkPlugin};
+use crate::options::{game_options, GameRng};
+use crate::pause::PausePlugin;
 use crate::player::PlayerPlugin;
+use crate::round::RoundPlugin;
+use crate::scoreboard::ScoreboardPlugin;
+use crate::settings::{game_settings, SettingsPlugin};
+use crate::team::TeamPlugin;
+use crate::ui::UiPlugin;
+use crate::weapons::WeaponsPlugin;
 
 use bevy::app::AppBuilder;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::ecs::schedule::ShouldRun;
 use bevy::prelude::*;
 
+pub use crate::animation::Animation;
+pub use crate::net::{NetworkConditions, NetworkMode};
+pub use crate::options::GameOptions;
+pub use crate::round::RoundPhase;
+pub use crate::settings::Settings;
+pub use crate::team::Team;
+
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
 // Or https://github.com/bevyengine/bevy/blob/main/examples/ecs/state.rs
@@ -25,17 +80,141 @@ enum GameState {
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // Looking for servers on the local network
+    Browser,
+    // The pause menu, pushed on top of Playing
+    Paused,
+    // Pushed on top of the main or the pause menu
+    Settings,
+}
+
+// Which processes run a set of match systems. The network mode is checked every frame,
+// because the main menu can host or join a match after the game started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    Everyone,
+    // Offline games and servers, they run the simulation
+    Authority,
+    Server,
+    // Clients mirror the server, also while they pick one in the LAN browser
+    Client,
+}
+
+impl Role {
+    fn includes(&self, mode: &NetworkMode) -> bool {
+        match self {
+            Role::Everyone => true,
+            Role::Authority => mode.is_authority(),
+            Role::Server => mode.is_server(),
+            Role::Client => !mode.is_authority(),
+        }
+    }
+}
+
+// Run criteria of the Playing state, labeled once so the role filters can be piped after them
+const PLAYING_ENTER: &str = "playing_enter";
+const PLAYING_UPDATE: &str = "playing_update";
+const PLAYING_IN_STACK: &str = "playing_in_stack";
+
+// Systems the state lets through only run if the process has the role
+fn filter_role(run: ShouldRun, runs: bool) -> ShouldRun {
+    match run {
+        _ if runs => run,
+        ShouldRun::YesAndCheckAgain | ShouldRun::NoAndCheckAgain => ShouldRun::NoAndCheckAgain,
+        _ => ShouldRun::No,
+    }
+}
+
+fn playing(criteria: &'static str, role: Role) -> SystemSet {
+    SystemSet::new().with_run_criteria(RunCriteria::pipe(
+        criteria,
+        (move |In(run): In<ShouldRun>, mode: Res<NetworkMode>| {
+            filter_role(run, role.includes(&mode))
+        })
+        .system(),
+    ))
+}
+
+// Systems of the running match. In single-player the pause menu freezes them,
+// networked matches keep running underneath it.
+fn gameplay_update(role: Role) -> SystemSet {
+    SystemSet::new().with_run_criteria(RunCriteria::pipe(
+        PLAYING_IN_STACK,
+        (move |In(run): In<ShouldRun>, mode: Res<NetworkMode>, state: Res<State<GameState>>| {
+            let frozen = *mode == NetworkMode::Offline && *state.current() != GameState::Playing;
+            filter_role(run, role.includes(&mode) && !frozen)
+        })
+        .system(),
+    ))
+}
+
+// Systems setting up the match for `role`
+fn gameplay_enter(role: Role) -> SystemSet {
+    playing(PLAYING_ENTER, role)
+}
+
+// Like gameplay_update, but paused in every network mode, e.g. for menus of the match
+fn playing_update(role: Role) -> SystemSet {
+    playing(PLAYING_UPDATE, role)
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_state(GameState::Loading)
+        let mode = network_mode(app);
+        let options = game_options(app);
+        let settings = game_settings(app);
+
+        app.insert_resource(options.clone())
+            .insert_resource(settings)
+            .insert_resource(GameRng::new(options.seed))
+            .insert_resource(mode.clone())
+            .add_state(GameState::Loading)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(State::on_enter(GameState::Playing).label(PLAYING_ENTER)),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(State::on_update(GameState::Playing).label(PLAYING_UPDATE)),
+            )
+            .add_system_set(SystemSet::new().with_run_criteria(
+                State::on_in_stack_update(GameState::Playing).label(PLAYING_IN_STACK),
+            ))
             .add_plugin(LoadingPlugin)
             .add_plugin(MapPlugin)
             .add_plugin(PlayerPlugin)
-            .add_plugin(ActionsPlugin);
+            .add_plugin(AnimationPlugin)
+            .add_plugin(CombatPlugin)
+            .add_plugin(ScoreboardPlugin)
+            .add_plugin(TeamPlugin)
+            .add_plugin(InternalAudioPlugin)
+            .add_plugin(NetworkPlugin);
+
+        // The match itself only runs where it is hosted, clients mirror the server
+        app.add_plugin(RoundPlugin)
+            .add_plugin(BombPlugin)
+            .add_plugin(EconomyPlugin)
+            .add_plugin(GrenadesPlugin)
+            .add_plugin(WeaponsPlugin)
+            .add_plugin(BotsPlugin);
+
+        // A dedicated server has nobody sitting in front of it. Everyone else can
+        // go back to the main menu, and host or join a match from there.
+        if !mode.is_headless() {
+            app.add_plugin(UiPlugin)
+                .add_plugin(ActionsPlugin)
+                .add_plugin(CameraPlugin)
+                .add_plugin(PausePlugin)
+                .add_plugin(SettingsPlugin)
+                .add_plugin(CrosshairPlugin)
+                .add_plugin(HudPlugin)
+                .add_plugin(MinimapPlugin)
+                .add_plugin(MenuPlugin)
+                .add_plugin(BrowserPlugin)
+                .add_plugin(BuyMenuPlugin);
+        }
 
         // #[cfg(debug_assertions)]
         // {
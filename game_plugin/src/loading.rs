@@ -1,16 +1,28 @@
 use csv::ReaderBuilder;
 use serde::Deserialize;
 
+use crate::animation::{Animation, FrameEvent, Sheet};
+use crate::audio::Sound;
+use crate::economy::ShopItem;
+use crate::map::TileType;
+use crate::net::{network_mode, NetworkMode};
+use crate::options::{game_options, GameOptions};
 use crate::GameState;
+use asefile::AsepriteFile;
 use bevy::{
-    asset::{AssetLoader, LoadContext, LoadedAsset},
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
     prelude::*,
     reflect::TypeUuid,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
     utils::BoxedFuture,
 };
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
 
 use bevy_asset_loader::AssetCollection;
 use bevy_kira_audio::AudioSource;
+use rand::seq::SliceRandom;
 // use bevy_ecs_tilemap::prelude::*;
 
 #[derive(Debug, Deserialize, TypeUuid)]
@@ -48,6 +60,317 @@ impl AssetLoader for MapAssetLoader {
     }
 }
 
+#[derive(Debug, TypeUuid)]
+#[uuid = "8f3e43a1-5c0e-4b5e-9b8a-2f4c6d1e7a90"]
+pub struct ShopAsset {
+    pub items: Vec<ShopItem>,
+    // The icon of each item by its name, for the items that have one
+    pub icons: HashMap<String, Handle<ColorMaterial>>,
+}
+
+// Without a renderer the icons are not loaded
+pub struct ShopAssetLoader {
+    pub icons: bool,
+}
+
+impl AssetLoader for ShopAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(bytes);
+            let mut items: Vec<ShopItem> = Vec::new();
+            let mut icons = HashMap::new();
+            let mut dependencies = Vec::new();
+
+            for result in rdr.deserialize() {
+                let item: ShopItem = result?;
+                if let Some(icon) = item.icon.as_ref().filter(|_| self.icons) {
+                    let path = AssetPath::new(PathBuf::from(icon), None);
+                    let material = ColorMaterial::texture(load_context.get_handle(path.get_id()));
+                    let label = format!("icon_{}", item.name);
+                    let material =
+                        load_context.set_labeled_asset(&label, LoadedAsset::new(material));
+                    icons.insert(item.name.clone(), material);
+                    dependencies.push(path);
+                }
+                items.push(item);
+            }
+
+            let shop_asset =
+                LoadedAsset::new(ShopAsset { items, icons }).with_dependencies(dependencies);
+            load_context.set_default_asset(shop_asset);
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["shop.csv"]
+    }
+}
+
+#[derive(Debug)]
+pub struct SoundFile {
+    pub source: Handle<AudioSource>,
+    pub volume: f32,
+}
+
+// Maps each sound to the files played for it. A sound can have its own set per surface,
+// an empty surface column is the set used everywhere else.
+#[derive(Debug, TypeUuid)]
+#[uuid = "d4a7c2e9-6b1f-4f3a-8e5d-0c9b7a2f6e14"]
+pub struct SoundAsset {
+    pub sounds: HashMap<(Sound, Option<TileType>), Vec<SoundFile>>,
+}
+
+impl SoundAsset {
+    // A random file of the set, so repeated sounds like footsteps vary. Only what is heard
+    // is random here, the game's own random numbers stay untouched.
+    pub fn pick(&self, sound: Sound, surface: Option<TileType>) -> Option<&SoundFile> {
+        self.sounds
+            .get(&(sound, surface))
+            .or_else(|| self.sounds.get(&(sound, None)))
+            .and_then(|files| files.choose(&mut rand::thread_rng()))
+    }
+}
+
+#[derive(Deserialize)]
+struct SoundRecord {
+    sound: Sound,
+    surface: Option<TileType>,
+    path: String,
+    volume: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct SoundAssetLoader;
+
+impl AssetLoader for SoundAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(bytes);
+            let mut sounds = HashMap::new();
+            let mut dependencies = Vec::new();
+
+            for result in rdr.deserialize() {
+                let record: SoundRecord = result?;
+                let path = AssetPath::new(PathBuf::from(record.path), None);
+                let file = SoundFile {
+                    source: load_context.get_handle(path.get_id()),
+                    volume: record.volume.unwrap_or(1.0),
+                };
+                sounds
+                    .entry((record.sound, record.surface))
+                    .or_insert_with(Vec::new)
+                    .push(file);
+                dependencies.push(path);
+            }
+
+            let sound_asset =
+                LoadedAsset::new(SoundAsset { sounds }).with_dependencies(dependencies);
+            load_context.set_default_asset(sound_asset);
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sounds.csv"]
+    }
+}
+
+// A sprite sheet drawn in Aseprite. Every frame becomes a tile of the atlas, in one row.
+// Tags name ranges of frames, durations are in seconds.
+#[derive(Debug, TypeUuid)]
+#[uuid = "a3c9e4d2-7f18-4b6e-9d05-e2b17c8f4a61"]
+pub struct AsepriteAsset {
+    pub atlas: Handle<TextureAtlas>,
+    pub durations: Vec<f32>,
+    pub tags: HashMap<String, Range<usize>>,
+}
+
+impl AsepriteAsset {
+    pub fn frames(&self, frames: &ClipFrames) -> Option<Range<usize>> {
+        let range = match frames {
+            ClipFrames::All => 0..self.durations.len(),
+            ClipFrames::Tag(tag) => self.tags.get(tag)?.clone(),
+            ClipFrames::Range(range) => range.clone(),
+        };
+        if range.is_empty() || range.end > self.durations.len() {
+            return None;
+        }
+        Some(range)
+    }
+}
+
+// Without a renderer there are no textures, only the timing of the frames is read
+pub struct AsepriteAssetLoader {
+    pub images: bool,
+}
+
+impl AssetLoader for AsepriteAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let ase = AsepriteFile::read(bytes)?;
+            let frames = ase.num_frames();
+            let durations = (0..frames)
+                .map(|frame| ase.frame(frame).duration() as f32 / 1000.0)
+                .collect();
+            let tags = (0..ase.num_tags())
+                .map(|tag| ase.tag(tag))
+                .map(|tag| {
+                    let range = tag.from_frame() as usize..tag.to_frame() as usize + 1;
+                    (tag.name().to_string(), range)
+                })
+                .collect();
+
+            let atlas = if self.images {
+                let (width, height) = (ase.width(), ase.height());
+                let row = 4 * width * frames as usize;
+                let mut data = vec![0; row * height];
+                for frame in 0..frames {
+                    let image = ase.frame(frame).image().into_raw();
+                    for y in 0..height {
+                        let start = y * row + 4 * width * frame as usize;
+                        data[start..start + 4 * width]
+                            .copy_from_slice(&image[y * 4 * width..(y + 1) * 4 * width]);
+                    }
+                }
+                let texture = Texture::new(
+                    Extent3d::new((width * frames as usize) as u32, height as u32, 1),
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rgba8UnormSrgb,
+                );
+                let texture = load_context.set_labeled_asset("texture", LoadedAsset::new(texture));
+                let tile_size = Vec2::new(width as f32, height as f32);
+                let atlas = TextureAtlas::from_grid(texture, tile_size, frames as usize, 1);
+                load_context.set_labeled_asset("atlas", LoadedAsset::new(atlas))
+            } else {
+                Handle::default()
+            };
+
+            load_context.set_default_asset(LoadedAsset::new(AsepriteAsset {
+                atlas,
+                durations,
+                tags,
+            }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ase", "aseprite"]
+    }
+}
+
+// Which frames of its sheet a clip shows
+#[derive(Debug)]
+pub enum ClipFrames {
+    All,
+    Tag(String),
+    Range(Range<usize>),
+}
+
+impl ClipFrames {
+    // Empty for the whole sheet, a frame index like `2`, a range like `0-1` or a tag name
+    fn parse(frames: &str) -> Self {
+        let frames = frames.trim();
+        if frames.is_empty() {
+            return ClipFrames::All;
+        }
+        let mut bounds = frames.splitn(2, '-').map(|bound| bound.parse::<usize>());
+        match (bounds.next(), bounds.next()) {
+            (Some(Ok(first)), None) => ClipFrames::Range(first..first + 1),
+            (Some(Ok(first)), Some(Ok(last))) => ClipFrames::Range(first..last + 1),
+            _ => ClipFrames::Tag(frames.to_string()),
+        }
+    }
+}
+
+// How long each frame shows comes from the sheet. Events fire when the clip reaches
+// their frame, counted from the first frame of the clip.
+#[derive(Debug)]
+pub struct AnimationClip {
+    pub sheet: Sheet,
+    pub frames: ClipFrames,
+    pub looping: bool,
+    pub events: Vec<(usize, FrameEvent)>,
+}
+
+#[derive(Debug, TypeUuid)]
+#[uuid = "5b2e8f61-3c7d-4a9e-b1f0-7d6a4c2e9b38"]
+pub struct AnimationAsset {
+    pub clips: HashMap<Animation, AnimationClip>,
+}
+
+#[derive(Deserialize)]
+struct AnimationRecord {
+    animation: Animation,
+    sheet: Sheet,
+    frames: String,
+    looping: bool,
+    events: String,
+}
+
+// Events are written like `1:Footstep 3:Footstep`
+fn parse_frame_events(events: &str) -> Result<Vec<(usize, FrameEvent)>, anyhow::Error> {
+    events
+        .split_whitespace()
+        .map(|event| {
+            let (frame, name) = event
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("frame event without a frame: {}", event))?;
+            let event = FrameEvent::from_name(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown frame event: {}", name))?;
+            Ok((frame.parse()?, event))
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct AnimationAssetLoader;
+
+impl AssetLoader for AnimationAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut rdr = ReaderBuilder::new().has_headers(true).from_reader(bytes);
+            let mut clips = HashMap::new();
+
+            for result in rdr.deserialize() {
+                let record: AnimationRecord = result?;
+                let clip = AnimationClip {
+                    sheet: record.sheet,
+                    frames: ClipFrames::parse(&record.frames),
+                    looping: record.looping,
+                    events: parse_frame_events(&record.events)?,
+                };
+                clips.insert(record.animation, clip);
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(AnimationAsset { clips }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anims.csv"]
+    }
+}
+
 pub struct LoadingPlugin;
 
 /// This plugin loads all assets using [AssetLoader] from a third party bevy plugin
@@ -55,12 +378,50 @@ pub struct LoadingPlugin;
 /// If interested, take a look at https://bevy-cheatbook.github.io/features/assets.html
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_asset::<MapAsset>().add_asset_loader(MapAssetLoader);
-        bevy_asset_loader::AssetLoader::new(GameState::Loading, GameState::Playing)
+        let mode = network_mode(app);
+
+        app.add_asset::<MapAsset>()
+            .add_asset_loader(MapAssetLoader)
+            .add_asset::<ShopAsset>()
+            .add_asset_loader(ShopAssetLoader {
+                icons: !mode.is_headless(),
+            })
+            .add_asset::<AnimationAsset>()
+            .add_asset_loader(AnimationAssetLoader)
+            .add_asset::<AsepriteAsset>()
+            .add_asset_loader(AsepriteAssetLoader {
+                images: !mode.is_headless(),
+            })
+            .add_system_set(SystemSet::on_enter(GameState::Loading).with_system(load_map.system()));
+
+        // Without a renderer only the game data is loaded from disk. The sprites of the
+        // gameplay entities still exist, they just point at textures that are never loaded.
+        if mode.is_headless() {
+            app.add_asset::<ColorMaterial>()
+                .add_asset::<TextureAtlas>()
+                .insert_resource(TextureAssets::headless());
+            bevy_asset_loader::AssetLoader::new(GameState::Loading, GameState::Playing)
+                .with_collection::<SheetAssets>()
+                .with_collection::<DataAssets>()
+                .build(app);
+            return;
+        }
+
+        app.add_asset::<SoundAsset>()
+            .add_asset_loader(SoundAssetLoader);
+
+        // Clients without a server pick one first
+        let next = match mode {
+            NetworkMode::Browse => GameState::Browser,
+            _ if game_options(app).menu => GameState::Menu,
+            _ => GameState::Playing,
+        };
+        bevy_asset_loader::AssetLoader::new(GameState::Loading, next)
             .with_collection::<FontAssets>()
             .with_collection::<AudioAssets>()
             .with_collection::<TextureAssets>()
-            .with_collection::<MapAssets>()
+            .with_collection::<SheetAssets>()
+            .with_collection::<DataAssets>()
             .build(app);
     }
 }
@@ -76,8 +437,8 @@ pub struct FontAssets {
 
 #[derive(AssetCollection)]
 pub struct AudioAssets {
-    #[asset(path = "audio/flying.ogg")]
-    pub flying: Handle<AudioSource>,
+    #[asset(path = "data/audio.sounds.csv")]
+    pub sounds: Handle<SoundAsset>,
 }
 
 #[derive(AssetCollection)]
@@ -86,24 +447,72 @@ pub struct TextureAssets {
     #[asset(path = "textures/templates/templates_map_one.png")]
     pub tileset: Handle<TextureAtlas>,
 
-    #[asset(texture_atlas(tile_size_x = 64., tile_size_y = 64., columns = 2, rows = 1))]
-    #[asset(path = "textures/character_one/character_one_sprite_animation_stay.png")]
-    pub player_stay: Handle<TextureAtlas>,
+    #[asset(path = "textures/character_one/character_one_sprite.png")]
+    pub player: Handle<Texture>,
+}
+
+impl TextureAssets {
+    fn headless() -> Self {
+        Self {
+            tileset: Handle::default(),
+            player: Handle::default(),
+        }
+    }
+}
 
-    #[asset(texture_atlas(tile_size_x = 64., tile_size_y = 64., columns = 7, rows = 1))]
-    #[asset(path = "textures/character_one/character_one_sprite_animation_walk.png")]
-    pub player_walk: Handle<TextureAtlas>,
+// The player's animations, also loaded without a renderer for the timing of their frames
+#[derive(AssetCollection)]
+pub struct SheetAssets {
+    #[asset(path = "textures/character_one/character_one_sprite_animation_stay.ase")]
+    pub player_stay: Handle<AsepriteAsset>,
 
-    #[asset(texture_atlas(tile_size_x = 64., tile_size_y = 64., columns = 3, rows = 1))]
-    #[asset(path = "textures/character_one/character_one_sprite_animation_jump.png")]
-    pub player_jump: Handle<TextureAtlas>,
+    #[asset(path = "textures/character_one/character_one_sprite_animation_walk.ase")]
+    pub player_walk: Handle<AsepriteAsset>,
 
-    #[asset(path = "textures/character_one/character_one_sprite.png")]
-    pub player: Handle<Texture>,
+    #[asset(path = "textures/character_one/character_one_sprite_animation_jump.ase")]
+    pub player_jump: Handle<AsepriteAsset>,
+}
+
+// The map picked with --map. It is not part of a collection because its path is only
+// known at runtime, the map is spawned as soon as it finished loading.
+pub struct MapHandle(pub Handle<MapAsset>);
+
+fn load_map(mut commands: Commands, asset_server: Res<AssetServer>, options: Res<GameOptions>) {
+    let handle = asset_server.load(options.map_path().as_str());
+    commands.insert_resource(MapHandle(handle));
 }
 
 #[derive(AssetCollection)]
-pub struct MapAssets {
-    #[asset(path = "textures/maps/map_one.csv")]
-    pub map_one: Handle<MapAsset>,
+pub struct DataAssets {
+    #[asset(path = "data/items.shop.csv")]
+    pub shop: Handle<ShopAsset>,
+    #[asset(path = "data/player.anims.csv")]
+    pub animations: Handle<AnimationAsset>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_frames_are_indices_ranges_or_tags() {
+        assert!(matches!(ClipFrames::parse(" "), ClipFrames::All));
+        assert!(matches!(ClipFrames::parse("2"), ClipFrames::Range(range) if range == (2..3)));
+        assert!(matches!(ClipFrames::parse("0-3"), ClipFrames::Range(range) if range == (0..4)));
+        assert!(matches!(ClipFrames::parse("walk"), ClipFrames::Tag(tag) if tag == "walk"));
+        assert!(matches!(ClipFrames::parse("1-x"), ClipFrames::Tag(tag) if tag == "1-x"));
+    }
+
+    #[test]
+    fn frame_events_need_a_frame_and_a_known_name() {
+        let events = parse_frame_events("1:Footstep  3:ReloadComplete").unwrap();
+        assert_eq!(
+            events,
+            vec![(1, FrameEvent::Footstep), (3, FrameEvent::ReloadComplete)]
+        );
+        assert!(parse_frame_events("").unwrap().is_empty());
+        assert!(parse_frame_events("Footstep").is_err());
+        assert!(parse_frame_events("1:Jump").is_err());
+        assert!(parse_frame_events("x:Footstep").is_err());
+    }
 }
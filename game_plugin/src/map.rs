@@ -1,21 +1,26 @@
 use crate::consts::*;
-use crate::loading::{MapAsset, MapAssets, TextureAssets};
-use crate::GameState;
+use crate::loading::{MapAsset, MapHandle, TextureAssets};
+use crate::team::Team;
+use crate::{gameplay_update, GameState, Role};
+use bevy::asset::LoadState;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum TileType {
     Ground,
     Special,
     Lava,
     TreeGround,
     Floor,
+    Spawn,
     Background,
 }
 
 impl TileType {
     fn get_tiletype_from_index(ind: u32) -> TileType {
         match ind {
+            0 => TileType::Spawn,
             4 => TileType::Floor,
             5 => TileType::Lava,
             9 => TileType::Ground,
@@ -24,14 +29,27 @@ impl TileType {
             _ => TileType::Background,
         }
     }
+
+    pub fn is_solid(&self) -> bool {
+        matches!(
+            self,
+            TileType::Ground | TileType::Floor | TileType::TreeGround | TileType::Special
+        )
+    }
+
+    // Tiles a player can stand or walk on, they pick the footstep sounds
+    pub fn is_surface(&self) -> bool {
+        self.is_solid() || *self == TileType::Lava
+    }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Coordinate {
     pub x: usize,
     pub y: usize,
 }
 impl Coordinate {
-    fn new(x: usize, y: usize) -> Self {
+    pub fn new(x: usize, y: usize) -> Self {
         Self { x, y }
     }
 }
@@ -66,13 +84,158 @@ impl Map {
         }
     }
 
-    fn coordinate_to_pixel(&self, pos: &Coordinate, bound_w: f32, bound_h: f32) -> Vec2 {
+    pub fn coordinate_to_pixel(&self, pos: &Coordinate, bound_w: f32, bound_h: f32) -> Vec2 {
         Vec2::new(
             pos.x as f32 / self.size.x * bound_w - 0.5 * bound_w + (0.5 * self.tile_size.x),
             0.5 * bound_h - pos.y as f32 / self.size.y * bound_h - (0.5 * self.tile_size.y),
         )
     }
 
+    pub fn pixel_to_coordinate(
+        &self,
+        pixel: Vec2,
+        bound_w: f32,
+        bound_h: f32,
+    ) -> Option<Coordinate> {
+        let x = ((pixel.x + 0.5 * bound_w) / self.tile_size.x).floor();
+        let y = ((0.5 * bound_h - pixel.y) / self.tile_size.y).floor();
+        if x < 0.0 || y < 0.0 || x >= self.size.x || y >= self.size.y {
+            return None;
+        }
+        Some(Coordinate::new(x as usize, y as usize))
+    }
+
+    pub fn tile(&self, pos: &Coordinate) -> Option<TileType> {
+        self.topology
+            .get(pos.y)
+            .and_then(|row| row.get(pos.x))
+            .map(|ind| TileType::get_tiletype_from_index(*ind))
+    }
+
+    // Everything outside of the arena counts as solid
+    pub fn is_solid_at(&self, pixel: Vec2) -> bool {
+        self.pixel_to_coordinate(pixel, ARENA_W, ARENA_H)
+            .and_then(|pos| self.tile(&pos))
+            .map_or(true, |tile| tile.is_solid())
+    }
+
+    // Walks the segment in quarter tile steps and checks every tile it passes
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let step = 0.25 * self.tile_size.x.min(self.tile_size.y);
+        let steps = (from.distance(to) / step).ceil() as usize;
+        (1..steps).all(|i| !self.is_solid_at(from.lerp(to, i as f32 / steps as f32)))
+    }
+
+    // Like line_of_sight, but counts the solid tiles the segment passes through.
+    // Outside of the arena nothing is counted.
+    pub fn solid_tiles_between(&self, from: Vec2, to: Vec2) -> usize {
+        let step = 0.25 * self.tile_size.x.min(self.tile_size.y);
+        let steps = (from.distance(to) / step).ceil() as usize;
+        let mut crossed: Vec<(usize, usize)> = Vec::new();
+        for i in 1..steps {
+            let pixel = from.lerp(to, i as f32 / steps as f32);
+            let pos = match self.pixel_to_coordinate(pixel, ARENA_W, ARENA_H) {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let solid = self.tile(&pos).map_or(false, |tile| tile.is_solid());
+            if solid && !crossed.contains(&(pos.x, pos.y)) {
+                crossed.push((pos.x, pos.y));
+            }
+        }
+        crossed.len()
+    }
+
+    // Lower left and upper right corner of the tiles, the map is centered on the origin
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let half = 0.5 * self.size * self.tile_size;
+        (-half, half)
+    }
+
+    // The tile under a player's feet, None while in the air or outside of the arena
+    pub fn surface_under(&self, position: Vec2) -> Option<TileType> {
+        let feet = position - Vec2::new(0.0, 0.5 * PLAYER_TILE_SIZE - 1.0);
+        self.pixel_to_coordinate(feet, ARENA_W, ARENA_H)
+            .and_then(|pos| self.tile(&pos))
+            .filter(|tile| tile.is_surface())
+    }
+
+    // Groups of touching Special tiles form bomb sites, groups touching a spawn area are ignored
+    pub fn bomb_sites(&self) -> Vec<Vec<Coordinate>> {
+        let mut visited = vec![vec![false; self.size.x as usize]; self.size.y as usize];
+        let mut sites = Vec::new();
+
+        for (y, row) in self.topology.iter().enumerate() {
+            for x in 0..row.len() {
+                let start = Coordinate::new(x, y);
+                if visited[y][x] || self.tile(&start) != Some(TileType::Special) {
+                    continue;
+                }
+
+                let mut site = Vec::new();
+                let mut touches_spawn = false;
+                let mut stack = vec![start];
+                visited[y][x] = true;
+                while let Some(pos) = stack.pop() {
+                    site.push(pos);
+                    for neighbour in self.neighbours(&pos) {
+                        match self.tile(&neighbour) {
+                            Some(TileType::Special) if !visited[neighbour.y][neighbour.x] => {
+                                visited[neighbour.y][neighbour.x] = true;
+                                stack.push(neighbour);
+                            }
+                            Some(TileType::Spawn) => touches_spawn = true,
+                            _ => {}
+                        }
+                    }
+                }
+
+                if !touches_spawn {
+                    sites.push(site);
+                }
+            }
+        }
+        sites
+    }
+
+    fn neighbours(&self, pos: &Coordinate) -> Vec<Coordinate> {
+        let mut neighbours = Vec::new();
+        for dy in -1i32..=1 {
+            for dx in -1i32..=1 {
+                let x = pos.x as i32 + dx;
+                let y = pos.y as i32 + dy;
+                if (dx, dy) == (0, 0) || x < 0 || y < 0 {
+                    continue;
+                }
+                if (x as f32) < self.size.x && (y as f32) < self.size.y {
+                    neighbours.push(Coordinate::new(x as usize, y as usize));
+                }
+            }
+        }
+        neighbours
+    }
+
+    // Spawn tiles in the left half of the map belong to the terrorists, the right half to the CTs
+    pub fn spawn_area(&self, team: Team) -> Vec<Vec2> {
+        let mut area = Vec::new();
+        for (y, row) in self.topology.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                if TileType::get_tiletype_from_index(*tile) != TileType::Spawn {
+                    continue;
+                }
+                let side = if (x as f32) < 0.5 * self.size.x {
+                    Team::Terrorists
+                } else {
+                    Team::CounterTerrorists
+                };
+                if side == team {
+                    area.push(self.coordinate_to_pixel(&Coordinate::new(x, y), ARENA_W, ARENA_H));
+                }
+            }
+        }
+        area
+    }
+
     fn get_transform_scale(&self) -> Vec2 {
         Vec2::new(
             self.tile_size.x / self.texture_tile_size,
@@ -85,27 +248,33 @@ pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(
-            SystemSet::on_enter(GameState::Playing)
-                .with_system(spawn_map.system())
-                .with_system(spawn_camera.system()),
-        );
+        app.add_system_set(gameplay_update(Role::Everyone).with_system(spawn_map.system()));
     }
 }
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
-}
-
+// Waits for the selected map to finish loading, then spawns it once
 fn spawn_map(
     mut commands: Commands,
     textures: Res<TextureAssets>,
-    maps: Res<MapAssets>,
+    handle: Res<MapHandle>,
     map_assets: Res<Assets<MapAsset>>,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<State<GameState>>,
+    spawned: Option<Res<Map>>,
 ) {
-    let map_asset = map_assets
-        .get(maps.map_one.clone())
-        .expect("Failed to find MapAsset");
+    if spawned.is_some() {
+        return;
+    }
+    let map_asset = match map_assets.get(handle.0.clone()) {
+        Some(map_asset) => map_asset,
+        None => {
+            if asset_server.get_load_state(handle.0.clone()) == LoadState::Failed {
+                error!("Failed to load the map, returning to the menu");
+                state.set(GameState::Menu).unwrap();
+            }
+            return;
+        }
+    };
 
     let map = Map::new(
         Vec2::new(MAP_W, MAP_H),
@@ -141,3 +310,64 @@ fn spawn_map(
 
     commands.insert_resource(map);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Indices of the tileset, see TileType::get_tiletype_from_index
+    const SPAWN: u32 = 0;
+    const EMPTY: u32 = 1;
+    const GROUND: u32 = 9;
+    const SPECIAL: u32 = 10;
+
+    // A 10x10 map filling the arena, every tile is 64 pixels wide
+    fn map(tiles: &[(usize, usize, u32)]) -> Map {
+        let mut topology = vec![vec![EMPTY; 10]; 10];
+        for (x, y, tile) in tiles {
+            topology[*y][*x] = *tile;
+        }
+        let tile_size = Vec2::new(ARENA_W / 10.0, ARENA_H / 10.0);
+        Map::new(
+            Vec2::new(10.0, 10.0),
+            tile_size,
+            topology,
+            Handle::default(),
+            32.0,
+        )
+    }
+
+    fn center(map: &Map, x: usize, y: usize) -> Vec2 {
+        map.coordinate_to_pixel(&Coordinate::new(x, y), ARENA_W, ARENA_H)
+    }
+
+    #[test]
+    fn touching_special_tiles_form_one_site() {
+        let map = map(&[
+            (1, 1, SPECIAL),
+            (2, 2, SPECIAL),
+            (7, 7, SPECIAL),
+            // Next to a spawn area, not a site
+            (5, 0, SPECIAL),
+            (6, 0, SPAWN),
+        ]);
+        let mut sites = map.bomb_sites();
+        sites.sort_by_key(|site| site.len());
+
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0], vec![Coordinate::new(7, 7)]);
+        assert_eq!(sites[1].len(), 2);
+        assert!(sites[1].contains(&Coordinate::new(1, 1)));
+        assert!(sites[1].contains(&Coordinate::new(2, 2)));
+    }
+
+    #[test]
+    fn walls_block_the_line_of_sight() {
+        let wall: Vec<(usize, usize, u32)> = (0..10).map(|y| (5, y, GROUND)).collect();
+        let map = map(&wall);
+
+        assert!(!map.line_of_sight(center(&map, 2, 2), center(&map, 8, 2)));
+        assert!(map.line_of_sight(center(&map, 2, 2), center(&map, 2, 8)));
+        assert!(map.line_of_sight(center(&map, 6, 0), center(&map, 9, 9)));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::consts::DEFAULT_PORT;
+use crate::loading::FontAssets;
+use crate::net::{start_server, NetworkConditions, NetworkMode};
+use crate::options::GameOptions;
+use crate::ui::{menu_button, text_bundle, ButtonMaterials, MenuSelection};
+use crate::GameState;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+pub struct MenuPlugin;
+
+// The main menu shown after loading when the game was started without a network option,
+// and after leaving a match. It is hidden while the settings screen is open on top of it.
+// Hosting or joining switches the network mode, the systems of the new role take over.
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(SystemSet::on_enter(GameState::Menu).with_system(spawn_menu.system()))
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu).with_system(activate_entry.system()),
+            )
+            .add_system_set(SystemSet::on_pause(GameState::Menu).with_system(close_menu.system()))
+            .add_system_set(SystemSet::on_resume(GameState::Menu).with_system(spawn_menu.system()))
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(close_menu.system()));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuEntry {
+    Play,
+    Host,
+    Join,
+    Settings,
+    Quit,
+}
+
+impl MenuEntry {
+    const ALL: [MenuEntry; 5] = [
+        MenuEntry::Play,
+        MenuEntry::Host,
+        MenuEntry::Join,
+        MenuEntry::Settings,
+        MenuEntry::Quit,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            MenuEntry::Play => "Play",
+            MenuEntry::Host => "Host",
+            MenuEntry::Join => "Join",
+            MenuEntry::Settings => "Settings",
+            MenuEntry::Quit => "Quit",
+        }
+    }
+}
+
+struct MenuUi;
+
+fn spawn_menu(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    mut selection: ResMut<MenuSelection>,
+) {
+    *selection = MenuSelection::default();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(MenuUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("CS Clone", fonts.fira_sans.clone(), 60.0));
+            for (index, entry) in MenuEntry::ALL.iter().enumerate() {
+                menu_button(
+                    parent,
+                    index,
+                    entry.label(),
+                    fonts.fira_sans.clone(),
+                    &button_materials,
+                );
+            }
+        });
+}
+
+fn activate_entry(
+    mut commands: Commands,
+    mut selection: ResMut<MenuSelection>,
+    mut state: ResMut<State<GameState>>,
+    mut mode: ResMut<NetworkMode>,
+    options: Res<GameOptions>,
+    conditions: Option<Res<NetworkConditions>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let entry = match selection.activated.take() {
+        Some(index) => MenuEntry::ALL[index],
+        None => return,
+    };
+    match entry {
+        MenuEntry::Play => state.set(GameState::Playing).unwrap(),
+        MenuEntry::Host => {
+            match start_server(DEFAULT_PORT, conditions.as_deref(), options.map.clone()) {
+                Ok(server) => {
+                    commands.insert_resource(server);
+                    *mode = NetworkMode::host();
+                    state.set(GameState::Playing).unwrap();
+                }
+                Err(error) => error!("Failed to host on port {}: {}", DEFAULT_PORT, error),
+            }
+        }
+        MenuEntry::Join => {
+            *mode = NetworkMode::Browse;
+            state.set(GameState::Browser).unwrap();
+        }
+        MenuEntry::Settings => state.push(GameState::Settings).unwrap(),
+        MenuEntry::Quit => exit.send(AppExit),
+    }
+}
+
+fn close_menu(mut commands: Commands, ui: Query<Entity, With<MenuUi>>) {
+    for entity in ui.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,311 @@
+use crate::combat::Dead;
+use crate::consts::{
+    ARENA_H, ARENA_W, MINIMAP_INTERVAL, MINIMAP_MARKER, MINIMAP_ROTATING_SCALE, MINIMAP_SIZE,
+};
+use crate::grenades::{can_see, Smoke};
+use crate::map::{Coordinate, Map};
+use crate::player::{LocalPlayer, Player, PlayerInput};
+use crate::round::{Round, RoundPhase};
+use crate::settings::Settings;
+use crate::team::Team;
+use crate::ui::ButtonMaterials;
+use crate::{gameplay_update, GameState, Role};
+use bevy::math::Mat2;
+use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+pub struct MinimapPlugin;
+
+// The minimap is drawn into a texture from the Map topology: solid and walkable tiles and
+// the bomb sites. On top of it are the local player, its living teammates and the enemies
+// where they were seen last. Enemies in sight are shown where they are.
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SeenEnemies>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(spawn_minimap.system()),
+            )
+            .add_system_set(
+                gameplay_update(Role::Everyone)
+                    .with_system(track_enemies.system())
+                    .with_system(draw_minimap.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_minimap.system()),
+            );
+    }
+}
+
+const OUTSIDE: [u8; 4] = [0, 0, 0, 0];
+const WALKABLE: [u8; 4] = [30, 30, 40, 200];
+const SOLID: [u8; 4] = [120, 120, 130, 220];
+const BOMB_SITE: [u8; 4] = [200, 70, 50, 220];
+const LOCAL_MARKER: [u8; 4] = [255, 255, 255, 255];
+const TEAMMATE_MARKER: [u8; 4] = [80, 200, 255, 255];
+const ENEMY_MARKER: [u8; 4] = [255, 60, 60, 255];
+const LAST_SEEN_MARKER: [u8; 4] = [160, 60, 60, 160];
+
+struct MinimapFrame;
+
+struct Minimap {
+    texture: Handle<Texture>,
+    // Colors of the map tiles, built once the map is there
+    tiles: Option<Vec<Vec<[u8; 4]>>>,
+    timer: Timer,
+}
+
+// Where the local player last saw each enemy, and whether it is in sight right now.
+// Forgotten when the enemy dies and during the freeze time of the next round.
+#[derive(Default)]
+struct SeenEnemies(HashMap<Entity, (Vec2, bool)>);
+
+fn spawn_minimap(
+    mut commands: Commands,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    button_materials: Res<ButtonMaterials>,
+) {
+    let texture = textures.add(Texture::new(
+        Extent3d::new(MINIMAP_SIZE as u32, MINIMAP_SIZE as u32, 1),
+        TextureDimension::D2,
+        vec![0; 4 * MINIMAP_SIZE * MINIMAP_SIZE],
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+    let size = MINIMAP_SIZE as f32;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(16.0),
+                    top: Val::Px(16.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(4.0)),
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(MinimapFrame)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(size), Val::Px(size)),
+                        ..Default::default()
+                    },
+                    material: materials.add(texture.clone().into()),
+                    ..Default::default()
+                })
+                .insert(Minimap {
+                    texture,
+                    tiles: None,
+                    timer: Timer::from_seconds(MINIMAP_INTERVAL, true),
+                });
+        });
+}
+
+fn tile_colors(map: &Map) -> Vec<Vec<[u8; 4]>> {
+    let sites: Vec<Coordinate> = map.bomb_sites().into_iter().flatten().collect();
+    map.topology
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            (0..row.len())
+                .map(|x| {
+                    let pos = Coordinate::new(x, y);
+                    match map.tile(&pos) {
+                        _ if sites.contains(&pos) => BOMB_SITE,
+                        Some(tile) if tile.is_solid() => SOLID,
+                        _ => WALKABLE,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Smoke hides enemies from the minimap like it does on screen
+fn track_enemies(
+    round: Res<Round>,
+    map: Option<Res<Map>>,
+    mut seen: ResMut<SeenEnemies>,
+    smokes: Query<(&Smoke, &Transform)>,
+    local: Query<(&Transform, &Team), (With<LocalPlayer>, Without<Dead>)>,
+    players: Query<(Entity, &Transform, &Team, Option<&Dead>), With<Player>>,
+) {
+    if round.phase == RoundPhase::FreezeTime {
+        seen.0.clear();
+    }
+    seen.0
+        .retain(|enemy, _| matches!(players.get(*enemy), Ok((.., None))));
+    for (_, in_sight) in seen.0.values_mut() {
+        *in_sight = false;
+    }
+
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    let (eye, local_team) = match local.single() {
+        Ok((transform, team)) => (transform.translation.truncate(), team),
+        Err(_) => return,
+    };
+    for (entity, transform, team, dead) in players.iter() {
+        if team == local_team || dead.is_some() {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        if can_see(&map, smokes.iter(), eye, position) {
+            seen.0.insert(entity, (position, true));
+        }
+    }
+}
+
+// Maps between world positions and minimap pixels
+struct View {
+    center: Vec2,
+    // World units per pixel
+    scale: f32,
+    // Turns world offsets into minimap offsets
+    rotation: Mat2,
+}
+
+impl View {
+    fn fixed() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            scale: ARENA_W.max(ARENA_H) / MINIMAP_SIZE as f32,
+            rotation: Mat2::from_angle(0.0),
+        }
+    }
+
+    // Centered on the player, the aim points up
+    fn rotating(position: Vec2, aim: Option<Vec2>) -> Self {
+        let direction = aim.map_or(Vec2::Y, |aim| aim - position);
+        let angle = if direction == Vec2::ZERO {
+            0.0
+        } else {
+            FRAC_PI_2 - direction.y.atan2(direction.x)
+        };
+        Self {
+            center: position,
+            scale: MINIMAP_ROTATING_SCALE,
+            rotation: Mat2::from_angle(angle),
+        }
+    }
+
+    fn to_world(&self, x: usize, y: usize) -> Vec2 {
+        let half = 0.5 * MINIMAP_SIZE as f32;
+        let offset = Vec2::new(x as f32 + 0.5 - half, half - y as f32 - 0.5) * self.scale;
+        self.center + self.rotation.transpose() * offset
+    }
+
+    fn to_pixel(&self, position: Vec2) -> (i32, i32) {
+        let half = 0.5 * MINIMAP_SIZE as f32;
+        let offset = self.rotation * (position - self.center) / self.scale;
+        (
+            (offset.x + half).floor() as i32,
+            (half - offset.y).floor() as i32,
+        )
+    }
+}
+
+// Markers partly outside of the minimap are cut off at its edge
+fn draw_marker(data: &mut [u8], view: &View, position: Vec2, color: [u8; 4]) {
+    let (center_x, center_y) = view.to_pixel(position);
+    let size = MINIMAP_SIZE as i32;
+    for y in center_y - MINIMAP_MARKER..=center_y + MINIMAP_MARKER {
+        for x in center_x - MINIMAP_MARKER..=center_x + MINIMAP_MARKER {
+            if x < 0 || y < 0 || x >= size || y >= size {
+                continue;
+            }
+            let index = 4 * (y * size + x) as usize;
+            data[index..index + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+fn draw_minimap(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    map: Option<Res<Map>>,
+    seen: Res<SeenEnemies>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut minimaps: Query<&mut Minimap>,
+    players: Query<
+        (&Transform, &Team, &PlayerInput, Option<&LocalPlayer>),
+        (With<Player>, Without<Dead>),
+    >,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    let mut minimap = match minimaps.single_mut() {
+        Ok(minimap) => minimap,
+        Err(_) => return,
+    };
+    if !minimap.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    if minimap.tiles.is_none() {
+        minimap.tiles = Some(tile_colors(&map));
+    }
+    let texture = match textures.get_mut(&minimap.texture) {
+        Some(texture) => texture,
+        None => return,
+    };
+    let tiles = minimap.tiles.as_ref().unwrap();
+
+    let local = players.iter().find(|(.., local)| local.is_some());
+    let view = match local {
+        Some((transform, _, input, _)) if settings.minimap.rotate => {
+            View::rotating(transform.translation.truncate(), input.aim)
+        }
+        _ => View::fixed(),
+    };
+
+    for y in 0..MINIMAP_SIZE {
+        for x in 0..MINIMAP_SIZE {
+            let color = map
+                .pixel_to_coordinate(view.to_world(x, y), ARENA_W, ARENA_H)
+                .and_then(|pos| tiles.get(pos.y).and_then(|row| row.get(pos.x)))
+                .copied()
+                .unwrap_or(OUTSIDE);
+            let index = 4 * (y * MINIMAP_SIZE + x);
+            texture.data[index..index + 4].copy_from_slice(&color);
+        }
+    }
+
+    // Enemies in sight are drawn over the ones that were only seen before
+    let mut enemies: Vec<&(Vec2, bool)> = seen.0.values().collect();
+    enemies.sort_by_key(|(_, in_sight)| *in_sight);
+    for (position, in_sight) in enemies {
+        let color = if *in_sight {
+            ENEMY_MARKER
+        } else {
+            LAST_SEEN_MARKER
+        };
+        draw_marker(&mut texture.data, &view, *position, color);
+    }
+    if let Some((transform, local_team, ..)) = local {
+        for (teammate, team, _, is_local) in players.iter() {
+            if team == local_team && is_local.is_none() {
+                let position = teammate.translation.truncate();
+                draw_marker(&mut texture.data, &view, position, TEAMMATE_MARKER);
+            }
+        }
+        let position = transform.translation.truncate();
+        draw_marker(&mut texture.data, &view, position, LOCAL_MARKER);
+    }
+}
+
+fn despawn_minimap(mut commands: Commands, frames: Query<Entity, With<MinimapFrame>>) {
+    for entity in frames.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
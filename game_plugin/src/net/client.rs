@@ -0,0 +1,361 @@
+use super::delta::SnapshotDelta;
+use super::interpolation::{interpolate_remote_players, Interpolation};
+use super::prediction::{predict_local_player, PredictionBuffer};
+use super::protocol::*;
+use super::stats::NetworkStats;
+use super::transport::Transport;
+use super::NetworkId;
+use crate::audio::{Sound, SoundEvent};
+use crate::combat::{Armor, Dead, Health};
+use crate::consts::{
+    CONNECT_RETRY, INTERPOLATION_DELAY, SERVER_TIMEOUT, SNAPSHOT_HISTORY, SNAPSHOT_RATE,
+};
+use crate::economy::{Inventory, Wallet, Weapon};
+use crate::player::{spawn_player, LocalPlayer};
+use crate::round::Round;
+use crate::scoreboard::{KillFeed, Scoreboard};
+use crate::team::Team;
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+
+pub struct NetClientPlugin;
+
+// A client sends its input and mirrors whatever the server reports. Players and the
+// round state come from the received snapshots. The own player is predicted,
+// remote players are interpolated between snapshots.
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // The round is run by the server, the client keeps a copy for the HUD and input rules
+        app.init_resource::<Round>()
+            .init_resource::<PredictionBuffer>()
+            .add_system_set(
+                gameplay_update(Role::Client)
+                    .with_system(connect.system())
+                    .with_system(predict_local_player.system())
+                    .with_system(receive_server_messages.system())
+                    .with_system(leave_timed_out_server.system())
+                    .with_system(apply_status.system())
+                    .with_system(apply_scoreboard.system())
+                    .with_system(interpolate_remote_players.system())
+                    .with_system(report_bandwidth.system()),
+            );
+    }
+}
+
+pub struct Client {
+    pub transport: Box<dyn Transport>,
+    pub server: SocketAddr,
+    pub name: String,
+    pub player_id: Option<u32>,
+    pub last_tick: u32,
+    // When the welcome or the newest snapshot arrived
+    pub(super) last_tick_time: f64,
+    pub stats: NetworkStats,
+    // Decoded snapshots, the baselines the server may encode against
+    history: VecDeque<WorldSnapshot>,
+    connect_timer: Timer,
+    // The newest status of the own player that was not applied yet
+    status: Option<PlayerStatus>,
+    // Likewise the newest scoreboard
+    scoreboard: Option<ScoreboardUpdate>,
+}
+
+impl Client {
+    pub fn new(transport: Box<dyn Transport>, server: SocketAddr, name: String) -> Self {
+        Self {
+            transport,
+            server,
+            name,
+            player_id: None,
+            last_tick: 0,
+            last_tick_time: 0.0,
+            stats: NetworkStats::default(),
+            history: VecDeque::new(),
+            connect_timer: Timer::from_seconds(CONNECT_RETRY, true),
+            status: None,
+            scoreboard: None,
+        }
+    }
+
+    pub(super) fn send(&mut self, message: &ClientMessage) {
+        let server = self.server;
+        let bytes = encode(message);
+        self.stats.sent(bytes.len());
+        self.transport.send(server, &bytes);
+    }
+
+    // The server tick remote players are currently shown at, they are interpolated
+    // in the past. The server rewinds to this tick to check our shots.
+    pub fn view_tick(&self, now: f64) -> Option<f32> {
+        if self.last_tick == 0 {
+            return None;
+        }
+        let behind = (now - INTERPOLATION_DELAY - self.last_tick_time) * SNAPSHOT_RATE as f64;
+        Some(self.last_tick as f32 + behind as f32)
+    }
+
+    // Snapshots older than the newest one are useless, and deltas against
+    // a baseline we no longer have cannot be decoded
+    fn decode_snapshot(&mut self, delta: &SnapshotDelta) -> Option<WorldSnapshot> {
+        if delta.tick <= self.last_tick {
+            return None;
+        }
+        let baseline = match delta.baseline {
+            0 => None,
+            tick => Some(self.history.iter().find(|snapshot| snapshot.tick == tick)?),
+        };
+        let snapshot = delta.apply(baseline)?;
+        self.stats.snapshot(delta.baseline == 0);
+        self.last_tick = snapshot.tick;
+        self.history.push_back(snapshot.clone());
+        if self.history.len() > SNAPSHOT_HISTORY {
+            self.history.pop_front();
+        }
+        Some(snapshot)
+    }
+}
+
+pub(super) fn connect(time: Res<Time>, mut client: ResMut<Client>) {
+    if client.player_id.is_some() || !client.connect_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let name = client.name.clone();
+    client.send(&ClientMessage::Connect { name });
+}
+
+// A server that closed or crashed sends nothing anymore, the match is over then
+pub(super) fn leave_timed_out_server(
+    mut commands: Commands,
+    time: Res<Time>,
+    client: Res<Client>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let now = time.seconds_since_startup();
+    if client.player_id.is_none() || now - client.last_tick_time <= SERVER_TIMEOUT {
+        return;
+    }
+    warn!("Lost the connection to {}", client.server);
+    commands.remove_resource::<Client>();
+    state.set(GameState::Menu).unwrap();
+}
+
+pub(super) fn receive_server_messages(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<Client>,
+    mut round: ResMut<Round>,
+    mut prediction: ResMut<PredictionBuffer>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut players: Query<(
+        Entity,
+        &NetworkId,
+        Option<&LocalPlayer>,
+        Option<&mut Interpolation>,
+        &mut Transform,
+        &mut Health,
+        &mut Visible,
+        Option<&Team>,
+        Option<&Dead>,
+    )>,
+) {
+    let server = client.server;
+    let mut latest = None;
+    while let Some((from, bytes)) = client.transport.receive() {
+        if from != server {
+            continue;
+        }
+        client.stats.received(bytes.len());
+        match decode::<ServerMessage>(&bytes) {
+            Some(ServerMessage::Welcome { player_id }) => {
+                client.player_id = Some(player_id);
+                client.last_tick_time = time.seconds_since_startup();
+            }
+            // Until the welcome arrives the own player would look like a remote one
+            Some(ServerMessage::Snapshot(delta)) if client.player_id.is_some() => {
+                if let Some(snapshot) = client.decode_snapshot(&delta) {
+                    latest = Some((snapshot, delta.input_ack));
+                }
+            }
+            Some(ServerMessage::Sounds(cues)) => {
+                for cue in cues {
+                    // The own movement is predicted, its sounds were played already
+                    let predicted =
+                        matches!(cue.sound, Sound::Jump | Sound::Land | Sound::Footstep);
+                    if predicted && cue.source.is_some() && cue.source == client.player_id {
+                        continue;
+                    }
+                    let source = players
+                        .iter_mut()
+                        .find(|(_, id, ..)| Some(id.0) == cue.source)
+                        .map(|(entity, ..)| entity);
+                    sounds.send(SoundEvent {
+                        sound: cue.sound,
+                        position: cue.position.map(Vec2::from),
+                        source,
+                        surface: cue.surface,
+                        loudness: cue.loudness,
+                    });
+                }
+            }
+            Some(ServerMessage::Status(status)) => client.status = Some(status),
+            Some(ServerMessage::Scoreboard(update)) => client.scoreboard = Some(update),
+            _ => {}
+        }
+    }
+    let (snapshot, input_ack) = match latest {
+        Some(latest) => latest,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+    client.last_tick_time = now;
+
+    round.phase = snapshot.round.phase;
+    round.timer = Timer::from_seconds(snapshot.round.seconds_left, false);
+    round.terrorist_score = snapshot.round.terrorist_score;
+    round.counter_terrorist_score = snapshot.round.counter_terrorist_score;
+
+    let mut seen = HashSet::new();
+    for (entity, id, local, interpolation, mut transform, mut health, mut visible, team, dead) in
+        players.iter_mut()
+    {
+        let state = match snapshot.players.iter().find(|state| state.id == id.0) {
+            Some(state) => state,
+            None => {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        };
+        seen.insert(state.id);
+
+        if health.current != state.health {
+            health.current = state.health;
+        }
+        visible.is_visible = !state.dead;
+        if local.is_some() {
+            let position = Vec2::from(state.position);
+            prediction.reconcile(input_ack, position, &mut transform);
+        } else if let Some(mut interpolation) = interpolation {
+            interpolation.push(now, state);
+        }
+        apply_state(&mut commands, entity, state, team.copied(), dead.is_some());
+    }
+
+    for state in snapshot.players.iter().filter(|s| !seen.contains(&s.id)) {
+        let position = Vec2::from(state.position);
+        let entity = spawn_player(&mut commands, position);
+        commands.entity(entity).insert(NetworkId(state.id));
+        if client.player_id == Some(state.id) {
+            commands
+                .entity(entity)
+                .insert(LocalPlayer)
+                .insert(Wallet { money: 0 })
+                .insert(Inventory::default());
+        } else {
+            commands
+                .entity(entity)
+                .insert(Interpolation::new(now, state));
+        }
+        apply_state(&mut commands, entity, state, None, false);
+    }
+}
+
+// Only what the HUD shows is known about the own weapon, it is never fired on a client
+fn apply_status(
+    mut client: ResMut<Client>,
+    mut players: Query<(&mut Armor, &mut Wallet, &mut Inventory), With<LocalPlayer>>,
+) {
+    let (mut armor, mut wallet, mut inventory) = match players.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let status = match client.status.take() {
+        Some(status) => status,
+        None => return,
+    };
+    if armor.0 != status.armor {
+        armor.0 = status.armor;
+    }
+    if wallet.money != status.money {
+        wallet.money = status.money;
+    }
+    let weapon = status.weapon.map(|weapon| Weapon {
+        name: weapon.name,
+        damage: 0.0,
+        fire_rate: 0.0,
+        magazine: 0,
+        ammo: weapon.ammo,
+        reserve: weapon.reserve,
+        cooldown: 0.0,
+        reloading: weapon.reloading,
+    });
+    let unchanged = match (&inventory.weapon, &weapon) {
+        (Some(old), Some(new)) => {
+            old.name == new.name
+                && old.ammo == new.ammo
+                && old.reserve == new.reserve
+                && old.reloading == new.reloading
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    if !unchanged {
+        inventory.weapon = weapon;
+    }
+}
+
+// Kills already in the feed are repeated by the server, they are left alone
+fn apply_scoreboard(
+    time: Res<Time>,
+    mut client: ResMut<Client>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut feed: ResMut<KillFeed>,
+) {
+    let update = match client.scoreboard.take() {
+        Some(update) => update,
+        None => return,
+    };
+    if scoreboard.rows != update.players {
+        scoreboard.rows = update.players;
+    }
+    let count = feed.count();
+    let now = time.seconds_since_startup();
+    for notice in update
+        .kills
+        .into_iter()
+        .filter(|notice| notice.number > count)
+    {
+        feed.add(notice, now);
+    }
+}
+
+fn report_bandwidth(time: Res<Time>, mut client: ResMut<Client>) {
+    if let Some(report) = client.stats.tick(time.delta()) {
+        if client.player_id.is_some() {
+            info!("Client {}", report);
+        }
+    }
+}
+
+// Components are only touched when they change, so change detection keeps working on clients
+fn apply_state(
+    commands: &mut Commands,
+    entity: Entity,
+    state: &PlayerState,
+    team: Option<Team>,
+    dead: bool,
+) {
+    let mut entity = commands.entity(entity);
+    if state.team != team {
+        match state.team {
+            Some(team) => entity.insert(team),
+            None => entity.remove::<Team>(),
+        };
+    }
+    if state.dead && !dead {
+        entity.insert(Dead);
+    } else if !state.dead && dead {
+        entity.remove::<Dead>();
+    }
+}
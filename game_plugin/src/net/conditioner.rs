@@ -0,0 +1,81 @@
+use super::transport::Transport;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+// Simulated bad network, used to try prediction and interpolation on a local machine
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConditions {
+    // One way delay in seconds
+    pub latency: f32,
+    // Random extra delay of up to this many seconds
+    pub jitter: f32,
+    // Chance between 0 and 1 that a packet is lost
+    pub packet_loss: f32,
+}
+
+impl NetworkConditions {
+    pub fn is_perfect(&self) -> bool {
+        self.latency <= 0.0 && self.jitter <= 0.0 && self.packet_loss <= 0.0
+    }
+}
+
+// Wraps another transport and delays or drops outgoing packets.
+// Only sending is affected, so conditioning both ends doubles the round trip.
+pub struct ConditionedTransport {
+    inner: Box<dyn Transport>,
+    conditions: NetworkConditions,
+    delayed: VecDeque<(Instant, SocketAddr, Vec<u8>)>,
+}
+
+impl ConditionedTransport {
+    pub fn new(inner: Box<dyn Transport>, conditions: NetworkConditions) -> Self {
+        Self {
+            inner,
+            conditions,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        let now = Instant::now();
+        // Jitter reorders packets, so the whole queue is searched
+        let mut index = 0;
+        while index < self.delayed.len() {
+            if self.delayed[index].0 <= now {
+                let (_, to, payload) = self.delayed.remove(index).unwrap();
+                self.inner.send(to, &payload);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+impl Transport for ConditionedTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr()
+    }
+
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < self.conditions.packet_loss {
+            return;
+        }
+        let jitter = if self.conditions.jitter > 0.0 {
+            rng.gen_range(0.0..self.conditions.jitter)
+        } else {
+            0.0
+        };
+        let delay = Duration::from_secs_f32(self.conditions.latency.max(0.0) + jitter);
+        self.delayed
+            .push_back((Instant::now() + delay, to, payload.to_vec()));
+        self.flush();
+    }
+
+    fn receive(&mut self) -> Option<(SocketAddr, Vec<u8>)> {
+        self.flush();
+        self.inner.receive()
+    }
+}
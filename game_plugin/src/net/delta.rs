@@ -0,0 +1,145 @@
+use super::protocol::{PlayerState, RoundState, WorldSnapshot};
+use crate::animation::Animation;
+use crate::team::Team;
+use serde::{Deserialize, Serialize};
+
+// Only the fields that differ from the baseline are sent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerDelta {
+    pub id: u32,
+    pub position: Option<[f32; 2]>,
+    pub flip_x: Option<bool>,
+    pub animation: Option<Animation>,
+    pub team: Option<Option<Team>>,
+    pub health: Option<f32>,
+    pub dead: Option<bool>,
+}
+
+impl PlayerDelta {
+    fn between(baseline: Option<&PlayerState>, current: &PlayerState) -> Option<Self> {
+        fn changed<T: PartialEq + Copy>(old: Option<T>, new: T) -> Option<T> {
+            match old {
+                Some(old) if old == new => None,
+                _ => Some(new),
+            }
+        }
+
+        let delta = Self {
+            id: current.id,
+            position: changed(baseline.map(|b| b.position), current.position),
+            flip_x: changed(baseline.map(|b| b.flip_x), current.flip_x),
+            animation: changed(baseline.map(|b| b.animation), current.animation),
+            team: changed(baseline.map(|b| b.team), current.team),
+            health: changed(baseline.map(|b| b.health), current.health),
+            dead: changed(baseline.map(|b| b.dead), current.dead),
+        };
+        let unchanged = delta.position.is_none()
+            && delta.flip_x.is_none()
+            && delta.animation.is_none()
+            && delta.team.is_none()
+            && delta.health.is_none()
+            && delta.dead.is_none();
+        if unchanged {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    // A player missing from the baseline has all fields set
+    fn apply(&self, baseline: Option<&PlayerState>) -> Option<PlayerState> {
+        Some(PlayerState {
+            id: self.id,
+            position: self.position.or_else(|| baseline.map(|b| b.position))?,
+            flip_x: self.flip_x.or_else(|| baseline.map(|b| b.flip_x))?,
+            animation: self.animation.or_else(|| baseline.map(|b| b.animation))?,
+            team: self.team.or_else(|| baseline.map(|b| b.team))?,
+            health: self.health.or_else(|| baseline.map(|b| b.health))?,
+            dead: self.dead.or_else(|| baseline.map(|b| b.dead))?,
+        })
+    }
+}
+
+// A snapshot encoded against the last one the client acknowledged.
+// Without a baseline (tick 0) every player and the round are sent in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    pub tick: u32,
+    pub baseline: u32,
+    // Sequence of the last input the server simulated for the receiving client
+    pub input_ack: u32,
+    pub round: Option<RoundState>,
+    pub players: Vec<PlayerDelta>,
+    pub removed: Vec<u32>,
+}
+
+impl SnapshotDelta {
+    pub fn between(
+        baseline: Option<&WorldSnapshot>,
+        current: &WorldSnapshot,
+        input_ack: u32,
+    ) -> Self {
+        let round = match baseline {
+            Some(baseline) if baseline.round == current.round => None,
+            _ => Some(current.round.clone()),
+        };
+        let players = current
+            .players
+            .iter()
+            .filter_map(|player| PlayerDelta::between(find(baseline, player.id), player))
+            .collect();
+        let removed = baseline
+            .map(|baseline| {
+                baseline
+                    .players
+                    .iter()
+                    .filter(|old| current.players.iter().all(|player| player.id != old.id))
+                    .map(|old| old.id)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            tick: current.tick,
+            baseline: baseline.map_or(0, |baseline| baseline.tick),
+            input_ack,
+            round,
+            players,
+            removed,
+        }
+    }
+
+    // Rebuilds the full snapshot, None if a player or the round cannot be restored
+    pub fn apply(&self, baseline: Option<&WorldSnapshot>) -> Option<WorldSnapshot> {
+        let mut players: Vec<PlayerState> = baseline
+            .map(|baseline| {
+                baseline
+                    .players
+                    .iter()
+                    .filter(|player| !self.removed.contains(&player.id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        for delta in self.players.iter() {
+            match players.iter_mut().find(|player| player.id == delta.id) {
+                Some(player) => *player = delta.apply(Some(player))?,
+                None => players.push(delta.apply(None)?),
+            }
+        }
+        let round = self
+            .round
+            .clone()
+            .or_else(|| baseline.map(|baseline| baseline.round.clone()))?;
+
+        Some(WorldSnapshot {
+            tick: self.tick,
+            players,
+            round,
+        })
+    }
+}
+
+fn find(snapshot: Option<&WorldSnapshot>, id: u32) -> Option<&PlayerState> {
+    snapshot?.players.iter().find(|player| player.id == id)
+}
@@ -0,0 +1,87 @@
+use super::protocol::PlayerState;
+use crate::animation::{Animation, PlayerAnim};
+use crate::consts::{INTERPOLATION_DELAY, SNAPSHOT_HISTORY};
+use crate::player::LocalPlayer;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+struct Sample {
+    time: f64,
+    position: Vec2,
+    flip_x: bool,
+    animation: Animation,
+}
+
+impl Sample {
+    fn new(time: f64, state: &PlayerState) -> Self {
+        Self {
+            time,
+            position: Vec2::from(state.position),
+            flip_x: state.flip_x,
+            animation: state.animation,
+        }
+    }
+}
+
+// Received states of a remote player. The player is shown slightly in the past,
+// between the two states around that moment, so it moves smoothly between snapshots.
+pub struct Interpolation {
+    samples: VecDeque<Sample>,
+}
+
+impl Interpolation {
+    pub fn new(time: f64, state: &PlayerState) -> Self {
+        let mut samples = VecDeque::new();
+        samples.push_back(Sample::new(time, state));
+        Self { samples }
+    }
+
+    pub fn push(&mut self, time: f64, state: &PlayerState) {
+        self.samples.push_back(Sample::new(time, state));
+        if self.samples.len() > SNAPSHOT_HISTORY {
+            self.samples.pop_front();
+        }
+    }
+
+    // Drops samples that are no longer needed and returns the state at `time`.
+    // Past the newest sample the player stays where it was last seen.
+    fn sample(&mut self, time: f64) -> Option<(Vec2, bool, Animation)> {
+        while self.samples.len() > 1 && self.samples[1].time <= time {
+            self.samples.pop_front();
+        }
+        let from = self.samples.front()?;
+        let position = match self.samples.get(1) {
+            Some(to) if time > from.time => {
+                let t = ((time - from.time) / (to.time - from.time)) as f32;
+                from.position.lerp(to.position, t)
+            }
+            _ => from.position,
+        };
+        Some((position, from.flip_x, from.animation))
+    }
+}
+
+pub(super) fn interpolate_remote_players(
+    time: Res<Time>,
+    mut players: Query<
+        (
+            &mut Interpolation,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &mut PlayerAnim,
+        ),
+        Without<LocalPlayer>,
+    >,
+) {
+    let render_time = time.seconds_since_startup() - INTERPOLATION_DELAY;
+    for (mut interpolation, mut transform, mut sprite, mut anim) in players.iter_mut() {
+        let (position, flip_x, animation) = match interpolation.sample(render_time) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        sprite.flip_x = flip_x;
+        anim.anim = animation;
+    }
+}
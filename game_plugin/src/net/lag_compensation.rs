@@ -0,0 +1,124 @@
+use crate::consts::{MAX_REWIND, SNAPSHOT_RATE};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+struct HitboxFrame {
+    tick: u32,
+    hitboxes: Vec<(Entity, Vec2)>,
+}
+
+// Where every living player was at each snapshot tick, so shots can be checked against
+// the world the shooter actually saw. Only the last MAX_REWIND seconds are kept.
+#[derive(Default)]
+pub struct HitboxHistory {
+    frames: VecDeque<HitboxFrame>,
+}
+
+impl HitboxHistory {
+    fn window() -> f32 {
+        MAX_REWIND * SNAPSHOT_RATE
+    }
+
+    pub fn record(&mut self, tick: u32, hitboxes: Vec<(Entity, Vec2)>) {
+        self.frames.push_back(HitboxFrame { tick, hitboxes });
+        while self.frames.len() > Self::window().ceil() as usize + 1 {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn newest_tick(&self) -> Option<u32> {
+        self.frames.back().map(|frame| frame.tick)
+    }
+
+    // Player positions at a possibly fractional tick. Ticks older than the rewind window
+    // are moved up to its start, so a lagging client cannot shoot too far into the past.
+    pub fn rewind(&self, tick: f32) -> Vec<(Entity, Vec2)> {
+        let newest = match self.newest_tick() {
+            Some(newest) => newest as f32,
+            None => return Vec::new(),
+        };
+        let tick = tick.min(newest).max(newest - Self::window());
+
+        let index = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.tick as f32 <= tick)
+            .unwrap_or(0);
+        let from = &self.frames[index];
+        let to = match self.frames.get(index + 1) {
+            Some(to) => to,
+            None => return from.hitboxes.clone(),
+        };
+        let t = ((tick - from.tick as f32) / (to.tick - from.tick) as f32).clamp(0.0, 1.0);
+
+        // Players that joined in between are not rewound, players that died are gone
+        from.hitboxes
+            .iter()
+            .filter_map(|(entity, start)| {
+                to.hitboxes
+                    .iter()
+                    .find(|(other, _)| other == entity)
+                    .map(|(_, end)| (*entity, start.lerp(*end, t)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::HITBOX_HALF_SIZE;
+
+    fn position(history: &HitboxHistory, target: Entity, tick: f32) -> Vec2 {
+        history
+            .rewind(tick)
+            .into_iter()
+            .find(|(entity, _)| *entity == target)
+            .map(|(_, position)| position)
+            .expect("Target is not in the history")
+    }
+
+    #[test]
+    fn interpolates_between_ticks() {
+        let target = Entity::new(1);
+        let mut history = HitboxHistory::default();
+        history.record(1, vec![(target, Vec2::new(0.0, 0.0))]);
+        history.record(2, vec![(target, Vec2::new(10.0, 20.0))]);
+
+        assert_eq!(position(&history, target, 1.0), Vec2::new(0.0, 0.0));
+        assert_eq!(position(&history, target, 1.5), Vec2::new(5.0, 10.0));
+        assert_eq!(position(&history, target, 2.0), Vec2::new(10.0, 20.0));
+    }
+
+    #[test]
+    fn clamps_to_the_rewind_window() {
+        let target = Entity::new(1);
+        let mut history = HitboxHistory::default();
+        for tick in 1..=20 {
+            history.record(tick, vec![(target, Vec2::new(tick as f32, 0.0))]);
+        }
+        let oldest = 20.0 - HitboxHistory::window();
+
+        assert_eq!(position(&history, target, 2.0), Vec2::new(oldest, 0.0));
+        assert_eq!(position(&history, target, 30.0), Vec2::new(20.0, 0.0));
+    }
+
+    #[test]
+    fn target_that_moved_away_is_still_hit() {
+        let target = Entity::new(1);
+        let seen = Vec2::new(100.0, 0.0);
+        let now = Vec2::new(100.0, 200.0);
+        let mut history = HitboxHistory::default();
+        for tick in 1..=3 {
+            history.record(tick, vec![(target, seen)]);
+        }
+        for tick in 4..=6 {
+            history.record(tick, vec![(target, now)]);
+        }
+
+        // The shooter aimed at where the target was when they saw it at tick 3
+        let hitbox = position(&history, target, 3.0);
+        assert!((hitbox - seen).abs().max_element() <= HITBOX_HALF_SIZE);
+        assert!((now - seen).abs().max_element() > HITBOX_HALF_SIZE);
+    }
+}
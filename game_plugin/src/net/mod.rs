@@ -0,0 +1,324 @@
+mod client;
+mod conditioner;
+mod delta;
+mod interpolation;
+mod lag_compensation;
+mod prediction;
+mod protocol;
+mod server;
+mod stats;
+mod transport;
+
+pub use client::{Client, NetClientPlugin};
+pub use conditioner::{ConditionedTransport, NetworkConditions};
+pub use delta::{PlayerDelta, SnapshotDelta};
+pub use interpolation::Interpolation;
+pub use lag_compensation::HitboxHistory;
+pub use prediction::PredictionBuffer;
+pub use protocol::*;
+pub use server::{ClientInfo, NetServerPlugin, Server};
+pub use stats::{BandwidthReport, NetworkStats};
+pub use transport::{LoopbackTransport, Transport, UdpTransport};
+
+use crate::consts::DEFAULT_PORT;
+use crate::options::game_options;
+use crate::settings::game_settings;
+use crate::GameState;
+use bevy::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr};
+
+// How this process takes part in a match. Inserted before the GamePlugin is added,
+// plugins use it to decide which systems they run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkMode {
+    Offline,
+    Host { port: u16 },
+    // A server without a window or a player of its own
+    Dedicated { port: u16 },
+    Client { server: SocketAddr },
+    // A client that picks its server in the LAN browser
+    Browse,
+}
+
+impl Default for NetworkMode {
+    fn default() -> Self {
+        NetworkMode::Offline
+    }
+}
+
+impl NetworkMode {
+    pub fn host() -> Self {
+        NetworkMode::Host { port: DEFAULT_PORT }
+    }
+
+    // The authority runs the simulation, everyone else mirrors it
+    pub fn is_authority(&self) -> bool {
+        !matches!(self, NetworkMode::Client { .. } | NetworkMode::Browse)
+    }
+
+    pub fn is_server(&self) -> bool {
+        matches!(
+            self,
+            NetworkMode::Host { .. } | NetworkMode::Dedicated { .. }
+        )
+    }
+
+    // Nothing is rendered, played or read from input devices
+    pub fn is_headless(&self) -> bool {
+        matches!(self, NetworkMode::Dedicated { .. })
+    }
+}
+
+// Wraps a transport in the simulated conditions, if any were requested
+fn conditioned(
+    conditions: Option<&NetworkConditions>,
+    transport: UdpTransport,
+) -> Box<dyn Transport> {
+    match conditions {
+        Some(conditions) if !conditions.is_perfect() => Box::new(ConditionedTransport::new(
+            Box::new(transport),
+            conditions.clone(),
+        )),
+        _ => Box::new(transport),
+    }
+}
+
+// Opens a server for the game started with --host or hosted from the main menu
+pub fn start_server(
+    port: u16,
+    conditions: Option<&NetworkConditions>,
+    map: String,
+) -> std::io::Result<Server> {
+    let transport = UdpTransport::bind((Ipv4Addr::UNSPECIFIED, port).into())?;
+    let mut server = Server::new(conditioned(conditions, transport));
+    server.map = map;
+    Ok(server)
+}
+
+// Plugins read the mode while they are built, so it has to be inserted before the GamePlugin
+pub fn network_mode(app: &mut AppBuilder) -> NetworkMode {
+    app.world()
+        .get_resource::<NetworkMode>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Identifies the same player on the server and all clients
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct NetworkId(pub u32);
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mode = network_mode(app);
+        let conditions = app.world().get_resource::<NetworkConditions>().cloned();
+
+        match mode {
+            NetworkMode::Offline => {}
+            NetworkMode::Host { port } | NetworkMode::Dedicated { port } => {
+                let server = start_server(port, conditions.as_ref(), game_options(app).map)
+                    .expect("Failed to bind server socket");
+                app.insert_resource(server);
+            }
+            NetworkMode::Client { server } => {
+                let transport = UdpTransport::bind((Ipv4Addr::UNSPECIFIED, 0).into())
+                    .expect("Failed to bind client socket");
+                let transport = conditioned(conditions.as_ref(), transport);
+                let name = game_settings(app).name;
+                app.insert_resource(Client::new(transport, server, name));
+            }
+            // The Client resource is inserted once a server was picked
+            NetworkMode::Browse => {}
+        }
+
+        // Games with a window can host or join from the main menu later on
+        app.add_plugin(NetServerPlugin).add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(leave_match.system()),
+        );
+        if !mode.is_headless() {
+            app.add_plugin(NetClientPlugin);
+        }
+    }
+}
+
+// Back in the main menu the game is offline again. The server notices a client leaving,
+// clients of a server that closed time out, see `leave_timed_out_server`.
+fn leave_match(
+    mut commands: Commands,
+    mut mode: ResMut<NetworkMode>,
+    client: Option<ResMut<Client>>,
+) {
+    if let Some(mut client) = client {
+        client.send(&ClientMessage::Disconnect);
+    }
+    commands.remove_resource::<Client>();
+    commands.remove_resource::<Server>();
+    commands.insert_resource(HitboxHistory::default());
+    commands.insert_resource(PredictionBuffer::default());
+    *mode = NetworkMode::Offline;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::SoundEvent;
+    use crate::combat::Health;
+    use crate::consts::{FIXED_TIMESTEP, SERVER_TIMEOUT};
+    use crate::player::{step_player, InputQueue, LocalPlayer, Player, PlayerInput};
+    use crate::round::Round;
+    use bevy::app::Events;
+    use bevy::ecs::schedule::{Stage, StateError};
+    use std::thread;
+    use std::time::Duration;
+
+    // Stands in for move_player, which needs the whole game around it
+    fn simulate_inputs(mut players: Query<(&mut InputQueue, &mut Transform)>) {
+        for (mut queue, mut transform) in players.iter_mut() {
+            while let Some((sequence, input)) = queue.commands.pop_front() {
+                step_player(&mut transform, &input, FIXED_TIMESTEP);
+                queue.last_processed = sequence;
+            }
+        }
+    }
+
+    struct Match {
+        server: World,
+        client: World,
+        server_stage: SystemStage,
+        client_stage: SystemStage,
+    }
+
+    impl Match {
+        fn new() -> Self {
+            let server_addr = (Ipv4Addr::LOCALHOST, DEFAULT_PORT).into();
+            let client_addr = (Ipv4Addr::LOCALHOST, DEFAULT_PORT + 1).into();
+            let (server_transport, client_transport) =
+                LoopbackTransport::pair(server_addr, client_addr);
+
+            let mut server = World::default();
+            server.insert_resource(Time::default());
+            server.insert_resource(Round::default());
+            server.insert_resource(HitboxHistory::default());
+            server.insert_resource(Server::new(Box::new(server_transport)));
+
+            let mut client = World::default();
+            client.insert_resource(Time::default());
+            client.insert_resource(Round::default());
+            client.insert_resource(PredictionBuffer::default());
+            client.insert_resource(Events::<SoundEvent>::default());
+            client.insert_resource(State::new(GameState::Playing));
+            let name = "Tester".to_string();
+            client.insert_resource(Client::new(Box::new(client_transport), server_addr, name));
+
+            Self {
+                server,
+                client,
+                server_stage: SystemStage::single_threaded()
+                    .with_system(server::receive_client_messages.system())
+                    .with_system(simulate_inputs.system())
+                    .with_system(server::send_snapshots.system()),
+                client_stage: SystemStage::single_threaded()
+                    .with_system(client::connect.system())
+                    .with_system(client::receive_server_messages.system())
+                    .with_system(client::leave_timed_out_server.system()),
+            }
+        }
+
+        // Runs both sides in real time until `done` holds, the timers of the
+        // connection and the snapshots take a few frames
+        fn run_until(&mut self, done: impl Fn(&mut Self) -> bool) {
+            for _ in 0..400 {
+                self.server.get_resource_mut::<Time>().unwrap().update();
+                self.client.get_resource_mut::<Time>().unwrap().update();
+                self.server_stage.run(&mut self.server);
+                self.client_stage.run(&mut self.client);
+                if done(self) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            panic!("The match did not get there in time");
+        }
+
+        fn local_player(&mut self) -> Option<(NetworkId, Vec3, f32)> {
+            self.client
+                .query_filtered::<(&NetworkId, &Transform, &Health), With<LocalPlayer>>()
+                .iter(&self.client)
+                .next()
+                .map(|(id, transform, health)| (*id, transform.translation, health.current))
+        }
+
+        fn server_player(&mut self) -> (Vec3, u32) {
+            self.server
+                .query_filtered::<(&Transform, &InputQueue), With<Player>>()
+                .iter(&self.server)
+                .next()
+                .map(|(transform, queue)| (transform.translation, queue.last_processed))
+                .expect("The server has no player")
+        }
+    }
+
+    #[test]
+    fn client_joins_and_follows_the_server_over_loopback() {
+        let mut game = Match::new();
+
+        // Connect, welcome, then the first snapshot spawns the own player
+        game.run_until(|game| game.local_player().is_some());
+        let client = game.client.get_resource::<Client>().unwrap();
+        let player_id = client.player_id.expect("No welcome arrived");
+        assert!(client.last_tick > 0);
+        let server = game.server.get_resource::<Server>().unwrap();
+        let info = server.clients.values().next().unwrap();
+        assert_eq!(info.player_id, player_id);
+        assert_eq!(info.name, "Tester");
+        let (id, ..) = game.local_player().unwrap();
+        assert_eq!(id, NetworkId(player_id));
+
+        // Inputs acknowledging the first snapshot make it the baseline of the next ones
+        let input = PlayerInput {
+            movement: Some(Vec2::new(1.0, 0.0)),
+            ..Default::default()
+        };
+        let mut client = game.client.get_resource_mut::<Client>().unwrap();
+        let last_snapshot = client.last_tick;
+        client.send(&ClientMessage::Input {
+            commands: (1..=3)
+                .map(|sequence| InputCommand::new(sequence, &input))
+                .collect(),
+            last_snapshot,
+        });
+        game.run_until(|game| game.server_player().1 == 3);
+        let mut healths = game.server.query_filtered::<&mut Health, With<Player>>();
+        let mut health = healths.iter_mut(&mut game.server).next().unwrap();
+        health.current = 40.0;
+
+        // Only the health and the position changed, the rest comes from the baseline
+        game.run_until(|game| game.local_player().map(|(.., health)| health) == Some(40.0));
+        let (_, position, _) = game.local_player().unwrap();
+        let (server_position, _) = game.server_player();
+        assert_eq!(position, server_position);
+        let mut client = game.client.get_resource_mut::<Client>().unwrap();
+        let report = client.stats.tick(Duration::from_secs(60)).unwrap();
+        assert!(report.full_snapshots >= 1);
+        assert!(report.delta_snapshots >= 1);
+    }
+
+    #[test]
+    fn client_leaves_a_server_that_stopped_sending() {
+        let mut game = Match::new();
+        game.run_until(|game| game.local_player().is_some());
+
+        // The server is not run anymore, as if it was closed. Instead of waiting
+        // for the timeout the last snapshot is made older.
+        let mut client = game.client.get_resource_mut::<Client>().unwrap();
+        client.last_tick_time -= SERVER_TIMEOUT + 1.0;
+        game.client_stage.run(&mut game.client);
+        assert!(game.client.get_resource::<Client>().is_none());
+        let mut state = game.client.get_resource_mut::<State<GameState>>().unwrap();
+        assert!(matches!(
+            state.set(GameState::Menu),
+            Err(StateError::StateAlreadyQueued)
+        ));
+    }
+}
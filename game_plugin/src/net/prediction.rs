@@ -0,0 +1,195 @@
+use super::client::Client;
+use super::protocol::{ClientMessage, InputCommand};
+use crate::audio::SoundEvent;
+use crate::combat::Dead;
+use crate::consts::{FIXED_TIMESTEP, INPUT_REDUNDANCY, MAX_PENDING_INPUTS, PREDICTION_TOLERANCE};
+use crate::map::Map;
+use crate::player::{
+    movement_sound, step_player, FixedStep, Footing, LastMovement, LocalPlayer, PlayerInput,
+};
+use crate::round::Round;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+struct PendingInput {
+    command: InputCommand,
+    input: PlayerInput,
+    // Whether the input moved the player, inputs during freeze time or while dead do not
+    moved: bool,
+    // Where the player ended up after this input
+    position: Vec3,
+}
+
+// Inputs the client already simulated for its own player but the server has not acknowledged yet
+#[derive(Default)]
+pub struct PredictionBuffer {
+    pending: VecDeque<PendingInput>,
+    sequence: u32,
+    fixed_step: FixedStep,
+    pub corrections: u32,
+}
+
+impl PredictionBuffer {
+    // Drops everything the server has simulated. If the server ended up somewhere else than
+    // predicted, the player is moved to the server position and the newer inputs are replayed.
+    pub fn reconcile(&mut self, ack: u32, server_position: Vec2, transform: &mut Transform) {
+        let mut predicted = None;
+        while self
+            .pending
+            .front()
+            .map_or(false, |pending| pending.command.sequence <= ack)
+        {
+            let pending = self.pending.pop_front().unwrap();
+            if pending.command.sequence == ack {
+                predicted = Some(pending.position);
+            }
+        }
+        if let Some(predicted) = predicted {
+            if predicted.truncate().distance(server_position) <= PREDICTION_TOLERANCE {
+                return;
+            }
+        }
+
+        self.corrections += 1;
+        transform.translation.x = server_position.x;
+        transform.translation.y = server_position.y;
+        for pending in self.pending.iter_mut() {
+            if pending.moved {
+                step_player(transform, &pending.input, FIXED_TIMESTEP);
+            }
+            pending.position = transform.translation;
+        }
+    }
+}
+
+// Simulates the local player right away with the same fixed steps the server uses
+// and sends every step's input, together with the previous few in case packets get lost.
+pub(super) fn predict_local_player(
+    time: Res<Time>,
+    round: Res<Round>,
+    map: Option<Res<Map>>,
+    mut client: ResMut<Client>,
+    mut buffer: ResMut<PredictionBuffer>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut players: Query<
+        (
+            Entity,
+            &PlayerInput,
+            Option<&Dead>,
+            &mut Transform,
+            &mut Footing,
+            &mut LastMovement,
+        ),
+        With<LocalPlayer>,
+    >,
+) {
+    let steps = buffer.fixed_step.advance(time.delta_seconds());
+    if client.player_id.is_none() || steps == 0 {
+        return;
+    }
+    let (entity, input, dead, mut transform, mut footing, mut last_movement) =
+        match players.single_mut() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+
+    let view_tick = client.view_tick(time.seconds_since_startup());
+    let moved = round.phase.allows_movement() && dead.is_none();
+    for _ in 0..steps {
+        last_movement.0 = Vec3::ZERO;
+        if moved {
+            let before = transform.translation;
+            step_player(&mut transform, input, FIXED_TIMESTEP);
+            last_movement.0 = transform.translation - before;
+            // The own movement sounds are heard right away, the server's are ignored
+            let sound = footing.step(last_movement.0);
+            if let (Some(sound), Some(map)) = (sound, map.as_deref()) {
+                let position = transform.translation.truncate();
+                if let Some(event) = movement_sound(sound, entity, position, input, map) {
+                    sounds.send(event);
+                }
+            }
+        }
+        buffer.sequence += 1;
+        let mut command = InputCommand::new(buffer.sequence, input);
+        command.view_tick = view_tick;
+        buffer.pending.push_back(PendingInput {
+            command,
+            input: input.clone(),
+            moved,
+            position: transform.translation,
+        });
+        if buffer.pending.len() > MAX_PENDING_INPUTS {
+            buffer.pending.pop_front();
+        }
+    }
+
+    let recent = buffer
+        .pending
+        .iter()
+        .rev()
+        .take(INPUT_REDUNDANCY)
+        .map(|pending| pending.command.clone())
+        .collect();
+    let last_snapshot = client.last_tick;
+    client.send(&ClientMessage::Input {
+        commands: recent,
+        last_snapshot,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::PLAYER_SPEED;
+
+    // Predicts `count` steps to the right starting at the origin
+    fn predicted(count: u32) -> (PredictionBuffer, Transform) {
+        let input = PlayerInput {
+            movement: Some(Vec2::new(1.0, 0.0)),
+            ..Default::default()
+        };
+        let mut buffer = PredictionBuffer::default();
+        let mut transform = Transform::default();
+        for sequence in 1..=count {
+            step_player(&mut transform, &input, FIXED_TIMESTEP);
+            buffer.pending.push_back(PendingInput {
+                command: InputCommand::new(sequence, &input),
+                input: input.clone(),
+                moved: true,
+                position: transform.translation,
+            });
+        }
+        (buffer, transform)
+    }
+
+    #[test]
+    fn agreeing_server_only_drops_acknowledged_inputs() {
+        let (mut buffer, mut transform) = predicted(3);
+        let expected = transform.translation;
+        let acknowledged = buffer.pending[0].position.truncate();
+
+        buffer.reconcile(1, acknowledged, &mut transform);
+
+        assert_eq!(buffer.corrections, 0);
+        assert_eq!(buffer.pending.len(), 2);
+        assert_eq!(transform.translation, expected);
+    }
+
+    #[test]
+    fn disagreeing_server_replays_newer_inputs() {
+        let (mut buffer, mut transform) = predicted(3);
+        let server_position = Vec2::new(0.0, 40.0);
+
+        buffer.reconcile(2, server_position, &mut transform);
+
+        assert_eq!(buffer.corrections, 1);
+        assert_eq!(buffer.pending.len(), 1);
+        let step = PLAYER_SPEED * FIXED_TIMESTEP;
+        assert_eq!(
+            transform.translation.truncate(),
+            server_position + Vec2::new(step, 0.0)
+        );
+        assert_eq!(buffer.pending[0].position, transform.translation);
+    }
+}
@@ -0,0 +1,167 @@
+use super::delta::SnapshotDelta;
+use crate::animation::Animation;
+use crate::audio::Sound;
+use crate::map::TileType;
+use crate::player::PlayerInput;
+use crate::round::{Round, RoundPhase};
+use crate::scoreboard::{KillNotice, ScoreRow};
+use crate::team::Team;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Every datagram carries exactly one message
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Connect {
+        name: String,
+    },
+    // LAN discovery query, answered with the server's Info
+    Discover {
+        sent: f64,
+    },
+    // The newest inputs, repeated a few times so a lost packet costs nothing,
+    // and the newest snapshot the client could decode
+    Input {
+        commands: Vec<InputCommand>,
+        last_snapshot: u32,
+    },
+    Disconnect,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome { player_id: u32 },
+    Snapshot(SnapshotDelta),
+    Info(ServerInfo),
+    // Sounds of the last frame. Like snapshots they may get lost, nobody misses an old sound.
+    Sounds(Vec<SoundCue>),
+    // What only the receiving client's own player needs to know, sent with every snapshot
+    Status(PlayerStatus),
+    // Sent with every change of the scoreboard, it repeats the latest kills in case one got lost
+    Scoreboard(ScoreboardUpdate),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoundCue {
+    pub sound: Sound,
+    pub position: Option<[f32; 2]>,
+    // Network id of the player making the sound
+    pub source: Option<u32>,
+    pub surface: Option<TileType>,
+    pub loudness: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreboardUpdate {
+    pub players: Vec<ScoreRow>,
+    pub kills: Vec<KillNotice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    pub armor: f32,
+    pub money: u32,
+    pub weapon: Option<WeaponStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponStatus {
+    pub name: String,
+    pub ammo: u32,
+    pub reserve: u32,
+    pub reloading: bool,
+}
+
+// Answer to a discovery query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub map: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub sent: f64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InputCommand {
+    pub sequence: u32,
+    pub movement: Option<[f32; 2]>,
+    pub use_held: bool,
+    pub sneak: bool,
+    pub throw_grenade: bool,
+    pub fire: bool,
+    pub aim: Option<[f32; 2]>,
+    pub view_tick: Option<f32>,
+}
+
+impl InputCommand {
+    pub fn new(sequence: u32, input: &PlayerInput) -> Self {
+        Self {
+            sequence,
+            movement: input.movement.map(|v| [v.x, v.y]),
+            use_held: input.use_held,
+            sneak: input.sneak,
+            throw_grenade: input.throw_grenade,
+            fire: input.fire,
+            aim: input.aim.map(|v| [v.x, v.y]),
+            view_tick: input.view_tick,
+        }
+    }
+
+    pub fn to_input(&self) -> PlayerInput {
+        PlayerInput {
+            movement: self.movement.map(Vec2::from),
+            use_held: self.use_held,
+            sneak: self.sneak,
+            throw_grenade: self.throw_grenade,
+            fire: self.fire,
+            aim: self.aim.map(Vec2::from),
+            view_tick: self.view_tick,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub id: u32,
+    pub position: [f32; 2],
+    pub flip_x: bool,
+    pub animation: Animation,
+    pub team: Option<Team>,
+    pub health: f32,
+    pub dead: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundState {
+    pub phase: RoundPhase,
+    pub seconds_left: f32,
+    pub terrorist_score: u32,
+    pub counter_terrorist_score: u32,
+}
+
+impl From<&Round> for RoundState {
+    fn from(round: &Round) -> Self {
+        Self {
+            phase: round.phase,
+            seconds_left: round.seconds_left(),
+            terrorist_score: round.terrorist_score,
+            counter_terrorist_score: round.counter_terrorist_score,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub tick: u32,
+    pub players: Vec<PlayerState>,
+    pub round: RoundState,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::serialize(message).expect("Failed to encode network message")
+}
+
+pub fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Option<T> {
+    bincode::deserialize(bytes).ok()
+}
@@ -0,0 +1,449 @@
+use super::delta::SnapshotDelta;
+use super::lag_compensation::HitboxHistory;
+use super::protocol::*;
+use super::stats::NetworkStats;
+use super::transport::Transport;
+use super::NetworkId;
+use crate::animation::PlayerAnim;
+use crate::audio::SoundEvent;
+use crate::combat::{Armor, Dead, Health};
+use crate::consts::{CLIENT_TIMEOUT, MAX_PLAYERS, SNAPSHOT_HISTORY, SNAPSHOT_RATE};
+use crate::economy::{Inventory, Wallet};
+use crate::player::{spawn_player, InputQueue, Player};
+use crate::round::Round;
+use crate::scoreboard::{KillFeed, PlayerName, Scoreboard};
+use crate::team::Team;
+use crate::{gameplay_update, Role};
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+
+pub struct NetServerPlugin;
+
+// The server owns the simulation. Clients send their input, the server queues it on
+// their player and regularly broadcasts a snapshot of all players.
+impl Plugin for NetServerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<HitboxHistory>().add_system_set(
+            gameplay_update(Role::Server)
+                .with_system(receive_client_messages.system())
+                .with_system(assign_network_ids.system())
+                .with_system(drop_timed_out_clients.system())
+                .with_system(send_snapshots.system())
+                .with_system(relay_sounds.system())
+                .with_system(send_scoreboard.system())
+                .with_system(report_bandwidth.system()),
+        );
+    }
+}
+
+pub struct ClientInfo {
+    pub player: Entity,
+    pub player_id: u32,
+    pub name: String,
+    pub last_sequence: u32,
+    // Newest snapshot the client acknowledged, the baseline for its deltas
+    pub last_snapshot: u32,
+    pub last_seen: f64,
+    // Seconds from sending a snapshot until the client acknowledged it
+    pub ping: f64,
+}
+
+pub struct Server {
+    pub transport: Box<dyn Transport>,
+    // Shown in the LAN browser
+    pub name: String,
+    pub map: String,
+    pub clients: HashMap<SocketAddr, ClientInfo>,
+    pub tick: u32,
+    pub stats: NetworkStats,
+    history: VecDeque<WorldSnapshot>,
+    // When the snapshots in the history were sent
+    sent_times: VecDeque<(u32, f64)>,
+    snapshot_timer: Timer,
+    next_id: u32,
+}
+
+impl Server {
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        let name = format!("Server on port {}", transport.local_addr().port());
+        Self {
+            transport,
+            name,
+            map: "map_one".to_string(),
+            clients: HashMap::new(),
+            tick: 0,
+            stats: NetworkStats::default(),
+            history: VecDeque::new(),
+            sent_times: VecDeque::new(),
+            snapshot_timer: Timer::from_seconds(1.0 / SNAPSHOT_RATE, true),
+            next_id: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn send(&mut self, to: SocketAddr, message: &ServerMessage) {
+        let bytes = encode(message);
+        self.stats.sent(bytes.len());
+        self.transport.send(to, &bytes);
+    }
+
+    fn snapshot(&self, tick: u32) -> Option<&WorldSnapshot> {
+        self.history.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    fn sent_time(&self, tick: u32) -> Option<f64> {
+        self.sent_times
+            .iter()
+            .find(|(sent_tick, _)| *sent_tick == tick)
+            .map(|(_, time)| *time)
+    }
+
+    // Round trip time in milliseconds of the client controlling `player`
+    pub fn ping(&self, player: Entity) -> Option<u32> {
+        self.clients
+            .values()
+            .find(|client| client.player == player)
+            .map(|client| (client.ping * 1000.0).round() as u32)
+    }
+}
+
+pub(super) fn receive_client_messages(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut server: ResMut<Server>,
+    mut queues: Query<&mut InputQueue>,
+    players: Query<Entity, With<Player>>,
+) {
+    let now = time.seconds_since_startup();
+    while let Some((from, bytes)) = server.transport.receive() {
+        server.stats.received(bytes.len());
+        let message = match decode::<ClientMessage>(&bytes) {
+            Some(message) => message,
+            None => continue,
+        };
+        if let Some(client) = server.clients.get_mut(&from) {
+            client.last_seen = now;
+        }
+
+        match message {
+            ClientMessage::Discover { sent } => {
+                let info = ServerInfo {
+                    name: server.name.clone(),
+                    map: server.map.clone(),
+                    players: players.iter().count() as u32,
+                    max_players: MAX_PLAYERS,
+                    sent,
+                };
+                server.send(from, &ServerMessage::Info(info));
+            }
+            ClientMessage::Connect { name } => {
+                // Connect is resent until the welcome arrives, only the first one spawns a player
+                let player_id = match server.clients.get(&from) {
+                    Some(client) => client.player_id,
+                    None if players.iter().count() as u32 >= MAX_PLAYERS => continue,
+                    None => {
+                        let player_id = server.next_id();
+                        let player = spawn_player(&mut commands, Vec2::ZERO);
+                        commands
+                            .entity(player)
+                            .insert(NetworkId(player_id))
+                            .insert(PlayerName(name.clone()))
+                            .insert(InputQueue::default());
+                        info!("{} joined from {}", name, from);
+                        server.clients.insert(
+                            from,
+                            ClientInfo {
+                                player,
+                                player_id,
+                                name,
+                                last_sequence: 0,
+                                last_snapshot: 0,
+                                last_seen: now,
+                                ping: 0.0,
+                            },
+                        );
+                        player_id
+                    }
+                };
+                server.send(from, &ServerMessage::Welcome { player_id });
+            }
+            ClientMessage::Input {
+                commands: mut received,
+                last_snapshot,
+            } => {
+                let sent = server.sent_time(last_snapshot);
+                let client = match server.clients.get_mut(&from) {
+                    Some(client) => client,
+                    None => continue,
+                };
+                if last_snapshot > client.last_snapshot {
+                    client.last_snapshot = last_snapshot;
+                    if let Some(sent) = sent {
+                        client.ping = now - sent;
+                    }
+                }
+                let mut queue = match queues.get_mut(client.player) {
+                    Ok(queue) => queue,
+                    Err(_) => continue,
+                };
+                // Redundant and reordered packets only add inputs we have not seen yet
+                received.sort_by_key(|command| command.sequence);
+                for command in received {
+                    if command.sequence > client.last_sequence {
+                        client.last_sequence = command.sequence;
+                        queue
+                            .commands
+                            .push_back((command.sequence, command.to_input()));
+                    }
+                }
+            }
+            ClientMessage::Disconnect => {
+                if let Some(client) = server.clients.remove(&from) {
+                    info!("{} left", client.name);
+                    commands.entity(client.player).despawn();
+                }
+            }
+        }
+    }
+}
+
+// Players that exist on the server itself (the host, bots) need an id as well
+fn assign_network_ids(
+    mut commands: Commands,
+    mut server: ResMut<Server>,
+    players: Query<Entity, (With<Player>, Without<NetworkId>)>,
+) {
+    for entity in players.iter() {
+        let id = server.next_id();
+        commands.entity(entity).insert(NetworkId(id));
+    }
+}
+
+fn drop_timed_out_clients(mut commands: Commands, time: Res<Time>, mut server: ResMut<Server>) {
+    let now = time.seconds_since_startup();
+    let timed_out: Vec<SocketAddr> = server
+        .clients
+        .iter()
+        .filter(|(_, client)| now - client.last_seen > CLIENT_TIMEOUT)
+        .map(|(addr, _)| *addr)
+        .collect();
+    for addr in timed_out {
+        if let Some(client) = server.clients.remove(&addr) {
+            info!("{} timed out", client.name);
+            commands.entity(client.player).despawn();
+        }
+    }
+}
+
+pub(super) fn send_snapshots(
+    time: Res<Time>,
+    round: Res<Round>,
+    mut server: ResMut<Server>,
+    mut hitboxes: ResMut<HitboxHistory>,
+    queues: Query<&InputQueue>,
+    statuses: Query<(&Armor, &Wallet, &Inventory)>,
+    players: Query<
+        (
+            Entity,
+            &NetworkId,
+            &Transform,
+            &TextureAtlasSprite,
+            &PlayerAnim,
+            &Health,
+            Option<&Team>,
+            Option<&Dead>,
+        ),
+        With<Player>,
+    >,
+) {
+    if !server.snapshot_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    server.tick += 1;
+
+    let snapshot = WorldSnapshot {
+        tick: server.tick,
+        players: players
+            .iter()
+            .map(
+                |(_, id, transform, sprite, anim, health, team, dead)| PlayerState {
+                    id: id.0,
+                    position: [transform.translation.x, transform.translation.y],
+                    flip_x: sprite.flip_x,
+                    animation: anim.anim,
+                    team: team.copied(),
+                    health: health.current,
+                    dead: dead.is_some(),
+                },
+            )
+            .collect(),
+        round: RoundState::from(&*round),
+    };
+
+    hitboxes.record(
+        server.tick,
+        players
+            .iter()
+            .filter(|(.., dead)| dead.is_none())
+            .map(|(entity, _, transform, ..)| (entity, transform.translation.truncate()))
+            .collect(),
+    );
+
+    // Every client gets the changes since the last snapshot it acknowledged
+    // and is told how far its own inputs were simulated
+    let clients: Vec<(SocketAddr, u32, u32, Entity)> = server
+        .clients
+        .iter()
+        .map(|(addr, client)| {
+            let ack = queues
+                .get(client.player)
+                .map(|queue| queue.last_processed)
+                .unwrap_or(0);
+            (*addr, client.last_snapshot, ack, client.player)
+        })
+        .collect();
+    for (addr, last_snapshot, ack, player) in clients {
+        let delta = SnapshotDelta::between(server.snapshot(last_snapshot), &snapshot, ack);
+        server.stats.snapshot(delta.baseline == 0);
+        server.send(addr, &ServerMessage::Snapshot(delta));
+        if let Ok((armor, wallet, inventory)) = statuses.get(player) {
+            let status = PlayerStatus {
+                armor: armor.0,
+                money: wallet.money,
+                weapon: inventory.weapon.as_ref().map(|weapon| WeaponStatus {
+                    name: weapon.name.clone(),
+                    ammo: weapon.ammo,
+                    reserve: weapon.reserve,
+                    reloading: weapon.reloading,
+                }),
+            };
+            server.send(addr, &ServerMessage::Status(status));
+        }
+    }
+
+    server.history.push_back(snapshot);
+    let now = time.seconds_since_startup();
+    let tick = server.tick;
+    server.sent_times.push_back((tick, now));
+    if server.history.len() > SNAPSHOT_HISTORY {
+        server.history.pop_front();
+        server.sent_times.pop_front();
+    }
+}
+
+fn relay_sounds(
+    mut server: ResMut<Server>,
+    mut sounds: EventReader<SoundEvent>,
+    ids: Query<&NetworkId>,
+) {
+    let cues: Vec<SoundCue> = sounds
+        .iter()
+        .map(|event| SoundCue {
+            sound: event.sound,
+            position: event.position.map(|position| [position.x, position.y]),
+            source: event
+                .source
+                .and_then(|source| ids.get(source).ok())
+                .map(|id| id.0),
+            surface: event.surface,
+            loudness: event.loudness,
+        })
+        .collect();
+    if cues.is_empty() {
+        return;
+    }
+    let message = ServerMessage::Sounds(cues);
+    let clients: Vec<SocketAddr> = server.clients.keys().copied().collect();
+    for addr in clients {
+        server.send(addr, &message);
+    }
+}
+
+fn send_scoreboard(mut server: ResMut<Server>, scoreboard: Res<Scoreboard>, feed: Res<KillFeed>) {
+    if !scoreboard.is_changed() && !feed.is_changed() {
+        return;
+    }
+    let message = ServerMessage::Scoreboard(ScoreboardUpdate {
+        players: scoreboard.rows.clone(),
+        kills: feed.notices(),
+    });
+    let clients: Vec<SocketAddr> = server.clients.keys().copied().collect();
+    for addr in clients {
+        server.send(addr, &message);
+    }
+}
+
+fn report_bandwidth(time: Res<Time>, mut server: ResMut<Server>) {
+    if let Some(report) = server.stats.tick(time.delta()) {
+        if !server.clients.is_empty() {
+            info!("Server {}", report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::browser::LanBrowser;
+    use crate::net::UdpTransport;
+    use bevy::ecs::schedule::Stage;
+    use std::net::Ipv4Addr;
+    use std::thread;
+    use std::time::Duration;
+
+    fn local_udp() -> UdpTransport {
+        UdpTransport::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap()
+    }
+
+    fn server_world() -> (World, SocketAddr) {
+        let server = Server::new(Box::new(local_udp()));
+        let addr = server.transport.local_addr();
+        let mut world = World::default();
+        world.insert_resource(Time::default());
+        world.insert_resource(server);
+        (world, addr)
+    }
+
+    #[test]
+    fn browser_lists_every_server_that_answers() {
+        let (mut first, first_addr) = server_world();
+        let (mut second, second_addr) = server_world();
+        // A stage remembers the world it ran on, every server needs its own
+        let mut first_stage = SystemStage::single(receive_client_messages.system());
+        let mut second_stage = SystemStage::single(receive_client_messages.system());
+
+        let mut browser = LanBrowser::new(local_udp());
+        browser.query(first_addr, 0.0);
+        browser.query(second_addr, 0.0);
+
+        // Datagrams on loopback take a moment to arrive
+        for _ in 0..100 {
+            first_stage.run(&mut first);
+            second_stage.run(&mut second);
+            browser.receive_answers(0.0);
+            if browser.servers.len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut listed: Vec<SocketAddr> =
+            browser.servers.iter().map(|server| server.addr).collect();
+        listed.sort();
+        let mut expected = vec![first_addr, second_addr];
+        expected.sort();
+        assert_eq!(listed, expected);
+        for server in &browser.servers {
+            assert_eq!(
+                server.info.name,
+                format!("Server on port {}", server.addr.port())
+            );
+            assert_eq!(server.info.players, 0);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::consts::BANDWIDTH_REPORT_INTERVAL;
+use bevy::prelude::*;
+use std::fmt;
+use std::time::Duration;
+
+// Traffic of one side of the connection over a report interval
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthReport {
+    pub seconds: f32,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+    pub packets_sent: usize,
+    pub packets_received: usize,
+    pub full_snapshots: usize,
+    pub delta_snapshots: usize,
+}
+
+impl BandwidthReport {
+    pub fn sent_per_second(&self) -> f32 {
+        self.bytes_sent as f32 / self.seconds.max(f32::EPSILON)
+    }
+
+    pub fn received_per_second(&self) -> f32 {
+        self.bytes_received as f32 / self.seconds.max(f32::EPSILON)
+    }
+}
+
+impl fmt::Display for BandwidthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "up {:.1} kB/s ({} packets), down {:.1} kB/s ({} packets), snapshots {} full / {} delta",
+            self.sent_per_second() / 1024.0,
+            self.packets_sent,
+            self.received_per_second() / 1024.0,
+            self.packets_received,
+            self.full_snapshots,
+            self.delta_snapshots,
+        )
+    }
+}
+
+// Counts the traffic of a Client or Server and hands out a report every interval
+pub struct NetworkStats {
+    current: BandwidthReport,
+    timer: Timer,
+}
+
+impl Default for NetworkStats {
+    fn default() -> Self {
+        Self {
+            current: BandwidthReport::default(),
+            timer: Timer::from_seconds(BANDWIDTH_REPORT_INTERVAL, true),
+        }
+    }
+}
+
+impl NetworkStats {
+    pub fn sent(&mut self, bytes: usize) {
+        self.current.bytes_sent += bytes;
+        self.current.packets_sent += 1;
+    }
+
+    pub fn received(&mut self, bytes: usize) {
+        self.current.bytes_received += bytes;
+        self.current.packets_received += 1;
+    }
+
+    pub fn snapshot(&mut self, full: bool) {
+        if full {
+            self.current.full_snapshots += 1;
+        } else {
+            self.current.delta_snapshots += 1;
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration) -> Option<BandwidthReport> {
+        if !self.timer.tick(delta).just_finished() {
+            return None;
+        }
+        let mut report = std::mem::take(&mut self.current);
+        report.seconds = self.timer.duration().as_secs_f32();
+        Some(report)
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+// Largest datagram we ever expect to receive
+const MAX_PACKET_SIZE: usize = 1400;
+
+// Unreliable, unordered datagrams between addresses.
+// The game only talks to a Transport, so the UDP socket can be swapped for an in-process loopback.
+pub trait Transport: Send + Sync {
+    fn local_addr(&self) -> SocketAddr;
+    fn send(&mut self, to: SocketAddr, payload: &[u8]);
+    fn receive(&mut self) -> Option<(SocketAddr, Vec<u8>)>;
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+}
+
+impl UdpTransport {
+    // Needed to send to broadcast addresses, used for LAN discovery
+    pub fn enable_broadcast(&self) -> std::io::Result<()> {
+        self.socket.set_broadcast(true)
+    }
+}
+
+impl Transport for UdpTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.socket.local_addr().expect("UDP socket has no address")
+    }
+
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) {
+        if let Err(error) = self.socket.send_to(payload, to) {
+            bevy::log::warn!("Failed to send packet to {}: {}", to, error);
+        }
+    }
+
+    fn receive(&mut self) -> Option<(SocketAddr, Vec<u8>)> {
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((len, from)) => return Some((from, self.buffer[..len].to_vec())),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return None,
+                // On some platforms an ICMP port unreachable from an earlier send shows up here
+                Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    bevy::log::warn!("Failed to receive packet: {}", error);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+type Queue = Arc<Mutex<VecDeque<(SocketAddr, Vec<u8>)>>>;
+
+// Two transports wired to each other in memory, used to run server and client in one process
+pub struct LoopbackTransport {
+    addr: SocketAddr,
+    inbox: Queue,
+    peer_addr: SocketAddr,
+    peer_inbox: Queue,
+}
+
+impl LoopbackTransport {
+    pub fn pair(first: SocketAddr, second: SocketAddr) -> (Self, Self) {
+        let first_inbox = Queue::default();
+        let second_inbox = Queue::default();
+        (
+            Self {
+                addr: first,
+                inbox: first_inbox.clone(),
+                peer_addr: second,
+                peer_inbox: second_inbox.clone(),
+            },
+            Self {
+                addr: second,
+                inbox: second_inbox,
+                peer_addr: first,
+                peer_inbox: first_inbox,
+            },
+        )
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn send(&mut self, to: SocketAddr, payload: &[u8]) {
+        if to == self.peer_addr {
+            self.peer_inbox
+                .lock()
+                .unwrap()
+                .push_back((self.addr, payload.to_vec()));
+        }
+    }
+
+    fn receive(&mut self) -> Option<(SocketAddr, Vec<u8>)> {
+        self.inbox.lock().unwrap().pop_front()
+    }
+}
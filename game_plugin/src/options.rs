@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// Launch options of a match, inserted before the GamePlugin is added
+#[derive(Debug, Clone)]
+pub struct GameOptions {
+    // Name of a map in assets/textures/maps, without the extension
+    pub map: String,
+    // Bots the authority adds to the match
+    pub bots: u32,
+    // Makes every random gameplay decision repeatable
+    pub seed: Option<u64>,
+    // Start in the main menu instead of going straight into the match
+    pub menu: bool,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            map: "map_one".to_string(),
+            bots: 0,
+            seed: None,
+            menu: false,
+        }
+    }
+}
+
+impl GameOptions {
+    pub fn map_path(&self) -> String {
+        format!("textures/maps/{}.csv", self.map)
+    }
+}
+
+// Like the network mode, plugins read the options while they are built
+pub fn game_options(app: &mut AppBuilder) -> GameOptions {
+    app.world()
+        .get_resource::<GameOptions>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+// All gameplay randomness comes from here, so a seed replays the same decisions
+pub struct GameRng(pub StdRng);
+
+impl GameRng {
+    pub fn new(seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => GameRng(StdRng::seed_from_u64(seed)),
+            None => GameRng(StdRng::from_entropy()),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::actions::Actions;
+use crate::loading::FontAssets;
+use crate::ui::{menu_button, text_bundle, ButtonMaterials, Gamepads, MenuSelection};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct PausePlugin;
+
+// Escape or the gamepad's start button pushes the pause menu on top of the match.
+// Whether the match keeps running underneath depends on the gameplay system sets.
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(open_pause_menu.system()),
+        )
+        .add_system_set(SystemSet::on_pause(GameState::Playing).with_system(stop_actions.system()))
+        .add_system_set(
+            SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_menu.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Paused).with_system(activate_entry.system()),
+        )
+        .add_system_set(
+            SystemSet::on_pause(GameState::Paused).with_system(close_pause_menu.system()),
+        )
+        .add_system_set(
+            SystemSet::on_resume(GameState::Paused).with_system(spawn_pause_menu.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Paused).with_system(close_pause_menu.system()),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseEntry {
+    Resume,
+    Settings,
+    QuitToMenu,
+}
+
+impl PauseEntry {
+    const ALL: [PauseEntry; 3] = [
+        PauseEntry::Resume,
+        PauseEntry::Settings,
+        PauseEntry::QuitToMenu,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            PauseEntry::Resume => "Resume",
+            PauseEntry::Settings => "Settings",
+            PauseEntry::QuitToMenu => "Quit to menu",
+        }
+    }
+}
+
+struct PauseUi;
+
+// The press is consumed, the state changes in the same frame and would see it again
+fn pause_pressed(
+    keyboard_input: &mut Input<KeyCode>,
+    gamepads: &Gamepads,
+    gamepad_input: &mut Input<GamepadButton>,
+) -> bool {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        return true;
+    }
+    for gamepad in gamepads.iter() {
+        let start = GamepadButton(*gamepad, GamepadButtonType::Start);
+        if gamepad_input.just_pressed(start) {
+            gamepad_input.reset(start);
+            return true;
+        }
+    }
+    false
+}
+
+fn open_pause_menu(
+    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut gamepad_input: ResMut<Input<GamepadButton>>,
+) {
+    if pause_pressed(&mut keyboard_input, &gamepads, &mut gamepad_input) {
+        state.push(GameState::Paused).unwrap();
+    }
+}
+
+// Input systems stop running while paused, so the last held keys would stick otherwise
+fn stop_actions(mut actions: ResMut<Actions>) {
+    *actions = Actions::default();
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    mut selection: ResMut<MenuSelection>,
+) {
+    *selection = MenuSelection::default();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(PauseUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Paused", fonts.fira_sans.clone(), 48.0));
+            for (index, entry) in PauseEntry::ALL.iter().enumerate() {
+                menu_button(
+                    parent,
+                    index,
+                    entry.label(),
+                    fonts.fira_sans.clone(),
+                    &button_materials,
+                );
+            }
+        });
+}
+
+fn activate_entry(
+    mut selection: ResMut<MenuSelection>,
+    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    mut gamepad_input: ResMut<Input<GamepadButton>>,
+) {
+    if pause_pressed(&mut keyboard_input, &gamepads, &mut gamepad_input) {
+        state.pop().unwrap();
+        return;
+    }
+    let entry = match selection.activated.take() {
+        Some(index) => PauseEntry::ALL[index],
+        None => return,
+    };
+    match entry {
+        PauseEntry::Resume => state.pop().unwrap(),
+        PauseEntry::Settings => state.push(GameState::Settings).unwrap(),
+        // Leaving Playing tears the match down, see the on_exit systems of the plugins
+        PauseEntry::QuitToMenu => state.replace(GameState::Menu).unwrap(),
+    }
+}
+
+fn close_pause_menu(mut commands: Commands, ui: Query<Entity, With<PauseUi>>) {
+    for entity in ui.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
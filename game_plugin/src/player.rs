@@ -1,140 +1,289 @@
 use crate::actions::Actions;
-use crate::consts::{ARENA_H, ARENA_W, PLAYER_TILE_SIZE};
-use crate::loading::TextureAssets;
-use crate::GameState;
+use crate::animation::{AnimationEvent, FrameEvent, PlayerAnim};
+use crate::audio::{Sound, SoundEvent};
+use crate::combat::{Armor, Dead, Health};
+use crate::consts::{
+    ARENA_H, ARENA_W, FIXED_TIMESTEP, MAX_QUEUED_INPUTS, PLAYER_SPEED, PLAYER_TILE_SIZE,
+    SNEAK_LOUDNESS,
+};
+use crate::map::Map;
+use crate::net::{network_mode, Interpolation};
+use crate::round::Round;
+use crate::scoreboard::PlayerName;
+use crate::settings::Settings;
+use crate::{gameplay_enter, gameplay_update, GameState, Role};
 use bevy::prelude::*;
+use std::collections::VecDeque;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Animation {
-    Stay,
-    Walk,
-    Jump,
+// How far a player actually moved in its last simulated step, the animation is picked from it
+#[derive(Default)]
+pub struct LastMovement(pub Vec3);
+
+// Vertical distance a player moved in the last fixed step, to notice jumps and landings.
+// Footsteps come from the walk animation.
+#[derive(Default)]
+pub struct Footing {
+    vertical: f32,
+}
+
+impl Footing {
+    pub fn step(&mut self, moved: Vec3) -> Option<Sound> {
+        let sound = if self.vertical == 0.0 && moved.y > 0.0 {
+            Some(Sound::Jump)
+        } else if self.vertical < 0.0 && moved.y == 0.0 {
+            Some(Sound::Land)
+        } else {
+            None
+        };
+        self.vertical = moved.y;
+        sound
+    }
 }
 
-pub struct PlayerAnim {
-    pub anim: Animation,
-    pub n_frames: usize,
+// Movement input holds players in the air, so only a tile under the feet makes a sound.
+// The tile picks the sound set, sneaking makes it quieter.
+pub fn movement_sound(
+    sound: Sound,
+    entity: Entity,
+    position: Vec2,
+    input: &PlayerInput,
+    map: &Map,
+) -> Option<SoundEvent> {
+    let surface = map.surface_under(position)?;
+    let loudness = if input.sneak { SNEAK_LOUDNESS } else { 1.0 };
+    let event = SoundEvent::at(sound, position, entity)
+        .on_surface(Some(surface))
+        .with_loudness(loudness);
+    Some(event)
 }
 
 pub struct Player;
+// The player controlled by this client's Actions
+pub struct LocalPlayer;
+
+// The input a player acts on this frame. The local player gets it from Actions,
+// remote players from the network and bots from whatever drives them.
+#[derive(Default, Clone)]
+pub struct PlayerInput {
+    pub movement: Option<Vec2>,
+    pub use_held: bool,
+    pub sneak: bool,
+    pub throw_grenade: bool,
+    pub fire: bool,
+    pub aim: Option<Vec2>,
+    // Server tick the player saw when giving this input, shots are checked against that moment
+    pub view_tick: Option<f32>,
+}
+
+impl From<&Actions> for PlayerInput {
+    fn from(actions: &Actions) -> Self {
+        Self {
+            movement: actions.player_movement,
+            use_held: actions.use_held,
+            sneak: actions.sneak,
+            throw_grenade: actions.throw_grenade,
+            fire: actions.fire,
+            aim: actions.aim,
+            view_tick: None,
+        }
+    }
+}
+
+// Inputs of a remote player waiting to be simulated, one per fixed step, in sequence order
+#[derive(Default)]
+pub struct InputQueue {
+    pub commands: VecDeque<(u32, PlayerInput)>,
+    pub last_processed: u32,
+}
+
+// Movement is simulated in fixed steps so clients can replay it exactly for prediction
+#[derive(Default)]
+pub struct FixedStep {
+    accumulator: f32,
+}
+
+impl FixedStep {
+    pub fn advance(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta;
+        let steps = (self.accumulator / FIXED_TIMESTEP) as u32;
+        self.accumulator -= steps as f32 * FIXED_TIMESTEP;
+        steps
+    }
+}
+
 pub struct PlayerPlugin;
 
+// Only the authority (offline game or server) moves players,
+// clients get their positions from the server. A dedicated server has no player of its own.
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(
-            SystemSet::on_enter(GameState::Playing).with_system(spawn_player.system()),
-        )
-        .add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(anim_player.system())
-                .with_system(move_player.system()),
-        );
+        let mode = network_mode(app);
+
+        app.init_resource::<FixedStep>()
+            .add_system_set(gameplay_update(Role::Everyone).with_system(footstep_sounds.system()))
+            .add_system_set(gameplay_update(Role::Authority).with_system(move_player.system()))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_players.system()),
+            );
+        if !mode.is_headless() {
+            app.add_system_set(
+                gameplay_enter(Role::Authority).with_system(spawn_local_player.system()),
+            )
+            .add_system_set(
+                gameplay_update(Role::Everyone).with_system(apply_local_input.system()),
+            );
+        }
     }
 }
 
-fn spawn_player(mut commands: Commands, textures: Res<TextureAssets>) {
+// Sprites are flipped while moving right
+pub fn facing(sprite: &TextureAtlasSprite) -> Vec2 {
+    if sprite.flip_x {
+        Vec2::X
+    } else {
+        -Vec2::X
+    }
+}
+
+// The sprite gets its atlas from the first animation played
+pub fn spawn_player(commands: &mut Commands, position: Vec2) -> Entity {
     commands
         .spawn_bundle(SpriteSheetBundle {
             sprite: TextureAtlasSprite::new(0),
-            texture_atlas: textures.player_stay.clone(),
-            transform: Transform::from_translation(Vec3::new(-100.0, 0.0, 2.0)),
+            transform: Transform::from_translation(position.extend(2.0)),
             ..Default::default()
         })
-        .insert(PlayerAnim {
-            anim: Animation::Stay,
-            n_frames: 2,
-        })
-        .insert(Timer::from_seconds(0.2, true))
-        .insert(Player);
+        .insert(PlayerAnim::default())
+        .insert(LastMovement::default())
+        .insert(Health::new(100.0))
+        .insert(Armor(0.0))
+        .insert(PlayerInput::default())
+        .insert(Footing::default())
+        .insert(Player)
+        .id()
 }
 
-fn anim_player(
-    time: Res<Time>,
-    mut query: Query<(&mut Timer, &mut TextureAtlasSprite, &PlayerAnim)>,
-) {
-    for (mut timer, mut sprite, player_anim) in query.iter_mut() {
-        timer.tick(time.delta());
-        if timer.finished() {
-            sprite.index = ((sprite.index as usize + 1) % player_anim.n_frames) as u32;
-        }
-    }
+fn spawn_local_player(mut commands: Commands, settings: Res<Settings>) {
+    let player = spawn_player(&mut commands, Vec2::new(-100.0, 0.0));
+    commands
+        .entity(player)
+        .insert(LocalPlayer)
+        .insert(PlayerName(settings.name.clone()));
 }
 
-fn move_player(
-    mut commands: Commands,
-    time: Res<Time>,
+fn apply_local_input(
     actions: Res<Actions>,
-    textures: Res<TextureAssets>,
-    mut player_query: Query<&mut Transform, With<Player>>,
-    mut player_anim: Query<(Entity, &mut PlayerAnim, &mut TextureAtlasSprite)>,
+    mut players: Query<&mut PlayerInput, With<LocalPlayer>>,
 ) {
-    if actions.player_movement.is_none() {
-        let speed = 150.;
-        let movement = Vec3::new(
-            0.0 * speed * time.delta_seconds(),
-            -1.0 * speed * time.delta_seconds(),
-            0.,
-        );
-
-        for mut player_transform in player_query.iter_mut() {
-            player_transform.translation += movement;
-            player_transform.translation.x = player_transform.translation.x.clamp(
-                0.5 * (-ARENA_W + PLAYER_TILE_SIZE),
-                0.5 * (ARENA_W - PLAYER_TILE_SIZE),
-            );
-            player_transform.translation.y = player_transform.translation.y.clamp(
-                0.5 * (-ARENA_H + PLAYER_TILE_SIZE),
-                0.5 * (ARENA_H - PLAYER_TILE_SIZE),
-            );
-        }
-        for (entity, mut anim, mut sprite) in player_anim.iter_mut() {
-            if anim.anim == Animation::Stay {
-                continue;
-            }
-            anim.anim = Animation::Stay;
-            anim.n_frames = 2;
-            sprite.index = 0;
-            commands.entity(entity).insert(textures.player_stay.clone());
-            
-        }
-        return;
+    for mut input in players.iter_mut() {
+        *input = PlayerInput::from(&*actions);
     }
+}
 
-    let speed = 150.;
-    let movement = Vec3::new(
-        actions.player_movement.unwrap().x * speed * time.delta_seconds(),
-        actions.player_movement.unwrap().y * speed * time.delta_seconds(),
-        0.,
+// Without input players fall down, any movement input overrides falling
+pub fn step_player(transform: &mut Transform, input: &PlayerInput, delta: f32) {
+    let direction = input.movement.unwrap_or_else(|| Vec2::new(0.0, -1.0));
+    let movement = (direction * PLAYER_SPEED * delta).extend(0.0);
+
+    transform.translation += movement;
+    transform.translation.x = transform.translation.x.clamp(
+        0.5 * (-ARENA_W + PLAYER_TILE_SIZE),
+        0.5 * (ARENA_W - PLAYER_TILE_SIZE),
+    );
+    transform.translation.y = transform.translation.y.clamp(
+        0.5 * (-ARENA_H + PLAYER_TILE_SIZE),
+        0.5 * (ARENA_H - PLAYER_TILE_SIZE),
     );
+}
 
-    for mut player_transform in player_query.iter_mut() {
-        player_transform.translation += movement;
-        player_transform.translation.x = player_transform.translation.x.clamp(
-            0.5 * (-ARENA_W + PLAYER_TILE_SIZE),
-            0.5 * (ARENA_W - PLAYER_TILE_SIZE),
-        );
-        player_transform.translation.y = player_transform.translation.y.clamp(
-            0.5 * (-ARENA_H + PLAYER_TILE_SIZE),
-            0.5 * (ARENA_H - PLAYER_TILE_SIZE),
-        );
+// Remote players on a client are heard through the server, like their other movement sounds
+fn footstep_sounds(
+    map: Option<Res<Map>>,
+    mut events: EventReader<AnimationEvent>,
+    mut sounds: EventWriter<SoundEvent>,
+    players: Query<(&Transform, &PlayerInput), (Without<Dead>, Without<Interpolation>)>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    for event in events.iter() {
+        if event.event != FrameEvent::Footstep {
+            continue;
+        }
+        let (transform, input) = match players.get(event.entity) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        let position = transform.translation.truncate();
+        if let Some(sound) = movement_sound(Sound::Footstep, event.entity, position, input, &map) {
+            sounds.send(sound);
+        }
     }
+}
 
-    for (entity, mut anim, mut sprite) in player_anim.iter_mut() {
-        sprite.flip_x = movement.x >= 0.0;
+// Remote players advance one step per received input, so their movement
+// matches what their client predicted. Everybody else uses the current PlayerInput.
+fn move_player(
+    time: Res<Time>,
+    round: Res<Round>,
+    map: Option<Res<Map>>,
+    mut fixed_step: ResMut<FixedStep>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut players: Query<
+        (
+            Entity,
+            &mut PlayerInput,
+            Option<&mut InputQueue>,
+            Option<&Dead>,
+            &mut Transform,
+            &mut Footing,
+            &mut LastMovement,
+        ),
+        With<Player>,
+    >,
+) {
+    let steps = fixed_step.advance(time.delta_seconds());
+    // Queued inputs are used up even while nobody may move, the client does the same
+    let frozen = !round.phase.allows_movement();
 
-        if movement.y > 0.0 {
-            if anim.anim != Animation::Jump{
-                sprite.index = 0;
+    for (entity, mut input, mut queue, dead, mut transform, mut footing, mut last_movement) in
+        players.iter_mut()
+    {
+        for _ in 0..steps {
+            if let Some(queue) = queue.as_mut() {
+                // Catch up when inputs piled up, e.g. after a lag spike
+                while queue.commands.len() > MAX_QUEUED_INPUTS {
+                    queue.commands.pop_front();
+                }
+                match queue.commands.pop_front() {
+                    Some((sequence, command)) => {
+                        queue.last_processed = sequence;
+                        *input = command;
+                    }
+                    None => break,
+                }
+            }
+            if frozen || dead.is_some() {
+                last_movement.0 = Vec3::ZERO;
+                continue;
+            }
+            let before = transform.translation;
+            step_player(&mut transform, &input, FIXED_TIMESTEP);
+            last_movement.0 = transform.translation - before;
+            let sound = footing.step(last_movement.0);
+            if let (Some(sound), Some(map)) = (sound, map.as_deref()) {
+                let position = transform.translation.truncate();
+                if let Some(event) = movement_sound(sound, entity, position, &input, map) {
+                    sounds.send(event);
+                }
             }
-            anim.anim = Animation::Jump;            
-            anim.n_frames = 3;
-            commands.entity(entity).insert(textures.player_jump.clone());
-            continue;            
-        }
-        if  movement.x != 0.0 {
-            anim.anim = Animation::Walk;
-            anim.n_frames = 7;
-            commands.entity(entity).insert(textures.player_walk.clone()); 
         }
     }
 }
+
+fn despawn_players(mut commands: Commands, players: Query<Entity, With<Player>>) {
+    for entity in players.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
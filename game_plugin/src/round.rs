@@ -0,0 +1,277 @@
+use crate::combat::{Dead, Health};
+use crate::player::Player;
+use crate::team::Team;
+use crate::{gameplay_enter, gameplay_update, Role};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct RoundPlugin;
+
+// The match runs as a small state machine inside GameState::Playing.
+// Every phase owns a timer, and the phase only changes in `update_round`,
+// other plugins listen to RoundStartEvent / RoundEndEvent instead of polling the phase.
+impl Plugin for RoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MatchConfig>()
+            .init_resource::<Round>()
+            .add_event::<RoundStartEvent>()
+            .add_event::<RoundEndEvent>()
+            .add_event::<HalftimeEvent>()
+            .add_event::<ObjectiveEvent>()
+            .add_system_set(gameplay_enter(Role::Authority).with_system(start_match.system()))
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(update_round.system())
+                    .with_system(reset_players.system()),
+            );
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum RoundPhase {
+    Warmup,
+    FreezeTime,
+    Live,
+    RoundEnd,
+    Halftime,
+    MatchEnd,
+}
+
+impl RoundPhase {
+    pub fn allows_movement(&self) -> bool {
+        !matches!(self, RoundPhase::FreezeTime | RoundPhase::MatchEnd)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RoundEndReason {
+    Elimination,
+    TimeExpired,
+    BombExploded,
+    BombDefused,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchConfig {
+    pub rounds_to_win: u32,
+    pub warmup_time: f32,
+    pub freeze_time: f32,
+    pub round_time: f32,
+    pub round_end_time: f32,
+    pub halftime_time: f32,
+    pub match_end_time: f32,
+    pub buy_time: f32,
+    pub friendly_fire: bool,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            rounds_to_win: 16,
+            warmup_time: 10.0,
+            freeze_time: 5.0,
+            round_time: 115.0,
+            round_end_time: 5.0,
+            halftime_time: 15.0,
+            match_end_time: 10.0,
+            buy_time: 20.0,
+            friendly_fire: false,
+        }
+    }
+}
+
+impl MatchConfig {
+    // Sides are swapped once the first half of the maximum number of rounds was played
+    pub fn halftime_after(&self) -> u32 {
+        self.rounds_to_win.saturating_sub(1)
+    }
+}
+
+pub struct Round {
+    pub phase: RoundPhase,
+    pub timer: Timer,
+    pub number: u32,
+    pub terrorist_score: u32,
+    pub counter_terrorist_score: u32,
+    pub last_result: Option<(Team, RoundEndReason)>,
+    pub bomb_planted: bool,
+}
+
+impl Default for Round {
+    fn default() -> Self {
+        Round::new(&MatchConfig::default())
+    }
+}
+
+impl Round {
+    pub fn new(config: &MatchConfig) -> Self {
+        Self {
+            phase: RoundPhase::Warmup,
+            timer: Timer::from_seconds(config.warmup_time, false),
+            number: 0,
+            terrorist_score: 0,
+            counter_terrorist_score: 0,
+            last_result: None,
+            bomb_planted: false,
+        }
+    }
+
+    pub fn score(&self, team: Team) -> u32 {
+        match team {
+            Team::Terrorists => self.terrorist_score,
+            Team::CounterTerrorists => self.counter_terrorist_score,
+        }
+    }
+
+    pub fn seconds_left(&self) -> f32 {
+        (self.timer.duration().as_secs_f32() - self.timer.elapsed_secs()).max(0.0)
+    }
+
+    fn enter(&mut self, phase: RoundPhase, seconds: f32) {
+        self.phase = phase;
+        self.timer = Timer::from_seconds(seconds, false);
+    }
+
+    fn add_win(&mut self, team: Team) {
+        match team {
+            Team::Terrorists => self.terrorist_score += 1,
+            Team::CounterTerrorists => self.counter_terrorist_score += 1,
+        }
+    }
+}
+
+pub struct RoundStartEvent {
+    pub number: u32,
+}
+
+pub struct RoundEndEvent {
+    pub winner: Team,
+    pub reason: RoundEndReason,
+}
+
+pub struct HalftimeEvent;
+
+// Sent by objective plugins (e.g. the bomb) to end the live round
+pub struct ObjectiveEvent {
+    pub winner: Team,
+    pub reason: RoundEndReason,
+}
+
+fn start_match(mut commands: Commands, config: Res<MatchConfig>) {
+    commands.insert_resource(Round::new(&config));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_round(
+    time: Res<Time>,
+    config: Res<MatchConfig>,
+    mut round: ResMut<Round>,
+    mut round_start: EventWriter<RoundStartEvent>,
+    mut round_end: EventWriter<RoundEndEvent>,
+    mut halftime: EventWriter<HalftimeEvent>,
+    mut objectives: EventReader<ObjectiveEvent>,
+    players: Query<(&Team, Option<&Dead>), With<Player>>,
+) {
+    round.timer.tick(time.delta());
+    let objective = objectives.iter().next().map(|o| (o.winner, o.reason));
+
+    match round.phase {
+        RoundPhase::Warmup | RoundPhase::Halftime => {
+            if round.timer.finished() {
+                start_next_round(&mut round, &config, &mut round_start);
+            }
+        }
+        RoundPhase::FreezeTime => {
+            if round.timer.finished() {
+                round.enter(RoundPhase::Live, config.round_time);
+            }
+        }
+        RoundPhase::Live => {
+            // Once the bomb is planted the round is decided by the bomb, unless the CTs die
+            let eliminated = eliminated_team(&players)
+                .filter(|team| !round.bomb_planted || *team == Team::CounterTerrorists);
+
+            let result = if objective.is_some() {
+                objective
+            } else if let Some(winner) = eliminated.map(|t| t.opponent()) {
+                Some((winner, RoundEndReason::Elimination))
+            } else if round.timer.finished() && !round.bomb_planted {
+                Some((Team::CounterTerrorists, RoundEndReason::TimeExpired))
+            } else {
+                None
+            };
+
+            if let Some((winner, reason)) = result {
+                round.add_win(winner);
+                round.last_result = Some((winner, reason));
+                round.enter(RoundPhase::RoundEnd, config.round_end_time);
+                round_end.send(RoundEndEvent { winner, reason });
+            }
+        }
+        RoundPhase::RoundEnd => {
+            if !round.timer.finished() {
+                return;
+            }
+            if round.terrorist_score >= config.rounds_to_win
+                || round.counter_terrorist_score >= config.rounds_to_win
+            {
+                round.enter(RoundPhase::MatchEnd, config.match_end_time);
+            } else if round.number == config.halftime_after() {
+                // Players keep their score when they switch sides
+                let round = &mut *round;
+                std::mem::swap(
+                    &mut round.terrorist_score,
+                    &mut round.counter_terrorist_score,
+                );
+                round.enter(RoundPhase::Halftime, config.halftime_time);
+                halftime.send(HalftimeEvent);
+            } else {
+                start_next_round(&mut round, &config, &mut round_start);
+            }
+        }
+        RoundPhase::MatchEnd => {
+            if round.timer.finished() {
+                *round = Round::new(&config);
+            }
+        }
+    }
+}
+
+fn start_next_round(
+    round: &mut Round,
+    config: &MatchConfig,
+    round_start: &mut EventWriter<RoundStartEvent>,
+) {
+    round.number += 1;
+    round.bomb_planted = false;
+    round.enter(RoundPhase::FreezeTime, config.freeze_time);
+    round_start.send(RoundStartEvent {
+        number: round.number,
+    });
+}
+
+// A team is eliminated when it has at least one player and all of them are dead
+fn eliminated_team(players: &Query<(&Team, Option<&Dead>), With<Player>>) -> Option<Team> {
+    [Team::Terrorists, Team::CounterTerrorists]
+        .iter()
+        .copied()
+        .find(|team| {
+            let mut members = players.iter().filter(|(t, _)| *t == team).peekable();
+            members.peek().is_some() && members.all(|(_, dead)| dead.is_some())
+        })
+}
+
+fn reset_players(
+    mut commands: Commands,
+    mut round_start: EventReader<RoundStartEvent>,
+    mut players: Query<(Entity, &mut Health, &mut Visible), With<Player>>,
+) {
+    if round_start.iter().last().is_none() {
+        return;
+    }
+    for (entity, mut health, mut visible) in players.iter_mut() {
+        health.reset();
+        visible.is_visible = true;
+        commands.entity(entity).remove::<Dead>();
+    }
+}
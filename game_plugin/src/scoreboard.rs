@@ -0,0 +1,526 @@
+use crate::actions::Actions;
+use crate::combat::{DamageEvent, DeathEvent};
+use crate::consts::{
+    ASSIST_SCORE, KILL_FEED_LENGTH, KILL_FEED_TIME, KILL_SCORE, SCOREBOARD_INTERVAL,
+};
+use crate::loading::{DataAssets, FontAssets, ShopAsset};
+use crate::net::{network_mode, Server};
+use crate::player::Player;
+use crate::round::RoundStartEvent;
+use crate::team::Team;
+use crate::ui::{text_bundle, ButtonMaterials};
+use crate::{gameplay_update, GameState, Role};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+pub struct ScoreboardPlugin;
+
+// Kills, deaths and assists are counted where the match runs, from the DeathEvents.
+// Clients get the scoreboard and the latest kills from the server.
+// The scoreboard shows while its key is held, the kill feed in the top right corner
+// lists the kills of the last seconds with the icon of the weapon.
+impl Plugin for ScoreboardPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let mode = network_mode(app);
+
+        app.init_resource::<Scoreboard>()
+            .init_resource::<KillFeed>()
+            .add_system_set(gameplay_update(Role::Everyone).with_system(expire_kills.system()))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(reset_scores.system()),
+            )
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(give_stats.system())
+                    .with_system(keep_score.system())
+                    .with_system(collect_scores.system()),
+            );
+        if !mode.is_headless() {
+            app.init_resource::<BombIcon>()
+                .add_system_set(
+                    SystemSet::on_enter(GameState::Playing).with_system(spawn_kill_feed.system()),
+                )
+                .add_system_set(
+                    gameplay_update(Role::Everyone)
+                        .with_system(show_scoreboard.system())
+                        .with_system(update_kill_feed.system()),
+                )
+                .add_system_set(
+                    SystemSet::on_exit(GameState::Playing).with_system(despawn_scoreboard.system()),
+                );
+        }
+    }
+}
+
+pub struct PlayerName(pub String);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub score: u32,
+}
+
+// Enemies that hurt the player since it spawned, they get an assist when somebody else
+// makes the kill
+#[derive(Default)]
+struct Attackers(Vec<Entity>);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreRow {
+    pub name: String,
+    pub team: Option<Team>,
+    pub stats: Stats,
+    // Round trip time in milliseconds, players on the server itself have none
+    pub ping: Option<u32>,
+}
+
+// Sorted by team, the best player of a team first
+#[derive(Default)]
+pub struct Scoreboard {
+    pub rows: Vec<ScoreRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KillNotice {
+    // Counts the kills of the match, clients tell new kills from the ones they know by it
+    pub number: u32,
+    // Nobody is credited for suicides and the bomb of a planter that left
+    pub killer: Option<String>,
+    pub killer_team: Option<Team>,
+    pub weapon: Option<String>,
+    pub victim: String,
+    pub victim_team: Option<Team>,
+}
+
+// The latest kills with the time they were added at
+#[derive(Default)]
+pub struct KillFeed {
+    pub kills: VecDeque<(KillNotice, f64)>,
+    count: u32,
+}
+
+impl KillFeed {
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    // Kills the feed has seen already are ignored, clients receive them repeatedly
+    pub fn add(&mut self, notice: KillNotice, now: f64) {
+        if notice.number <= self.count {
+            return;
+        }
+        self.count = notice.number;
+        self.kills.push_back((notice, now));
+        if self.kills.len() > KILL_FEED_LENGTH {
+            self.kills.pop_front();
+        }
+    }
+
+    pub fn notices(&self) -> Vec<KillNotice> {
+        self.kills
+            .iter()
+            .map(|(notice, _)| notice.clone())
+            .collect()
+    }
+}
+
+struct RefreshTimer(Timer);
+
+impl Default for RefreshTimer {
+    fn default() -> Self {
+        RefreshTimer(Timer::from_seconds(SCOREBOARD_INTERVAL, true))
+    }
+}
+
+fn give_stats(mut commands: Commands, joined: Query<Entity, Added<Player>>) {
+    for entity in joined.iter() {
+        commands
+            .entity(entity)
+            .insert(Stats::default())
+            .insert(Attackers::default());
+    }
+}
+
+fn enemies(players: &Query<(&PlayerName, Option<&Team>)>, a: Entity, b: Entity) -> bool {
+    matches!(
+        (players.get(a), players.get(b)),
+        (Ok((_, Some(a))), Ok((_, Some(b)))) if a != b
+    )
+}
+
+// Only killing an enemy counts, teamkills and suicides are just a death.
+// Everybody else who hurt the victim since it spawned gets an assist.
+fn keep_score(
+    time: Res<Time>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut deaths: EventReader<DeathEvent>,
+    mut round_start: EventReader<RoundStartEvent>,
+    mut feed: ResMut<KillFeed>,
+    players: Query<(&PlayerName, Option<&Team>)>,
+    mut scores: Query<(&mut Stats, &mut Attackers)>,
+) {
+    if round_start.iter().next().is_some() {
+        for (_, mut attackers) in scores.iter_mut() {
+            attackers.0.clear();
+        }
+    }
+    for damage in damage_events.iter() {
+        let attacker = match damage.attacker {
+            Some(attacker) if enemies(&players, attacker, damage.target) => attacker,
+            _ => continue,
+        };
+        if let Ok((_, mut attackers)) = scores.get_mut(damage.target) {
+            if !attackers.0.contains(&attacker) {
+                attackers.0.push(attacker);
+            }
+        }
+    }
+
+    for death in deaths.iter() {
+        let attackers = match scores.get_mut(death.entity) {
+            Ok((mut stats, mut attackers)) => {
+                stats.deaths += 1;
+                std::mem::take(&mut attackers.0)
+            }
+            Err(_) => continue,
+        };
+        let killer = death
+            .killer
+            .filter(|killer| enemies(&players, *killer, death.entity));
+        if let Some(Ok((mut stats, _))) = killer.map(|killer| scores.get_mut(killer)) {
+            stats.kills += 1;
+            stats.score += KILL_SCORE;
+        }
+        for attacker in attackers.into_iter().filter(|a| Some(*a) != killer) {
+            if let Ok((mut stats, _)) = scores.get_mut(attacker) {
+                stats.assists += 1;
+                stats.score += ASSIST_SCORE;
+            }
+        }
+
+        let (victim, victim_team) = match players.get(death.entity) {
+            Ok((name, team)) => (name.0.clone(), team.copied()),
+            Err(_) => continue,
+        };
+        let killer = death
+            .killer
+            .filter(|killer| *killer != death.entity)
+            .and_then(|killer| players.get(killer).ok());
+        let notice = KillNotice {
+            number: feed.count() + 1,
+            killer: killer.map(|(name, _)| name.0.clone()),
+            killer_team: killer.and_then(|(_, team)| team.copied()),
+            weapon: death.weapon.clone(),
+            victim,
+            victim_team,
+        };
+        feed.add(notice, time.seconds_since_startup());
+    }
+}
+
+fn team_order(team: Option<Team>) -> u8 {
+    match team {
+        Some(Team::Terrorists) => 0,
+        Some(Team::CounterTerrorists) => 1,
+        None => 2,
+    }
+}
+
+fn collect_scores(
+    time: Res<Time>,
+    mut timer: Local<RefreshTimer>,
+    server: Option<Res<Server>>,
+    mut scoreboard: ResMut<Scoreboard>,
+    players: Query<(Entity, &PlayerName, Option<&Team>, &Stats)>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let mut rows: Vec<ScoreRow> = players
+        .iter()
+        .map(|(entity, name, team, stats)| ScoreRow {
+            name: name.0.clone(),
+            team: team.copied(),
+            stats: *stats,
+            ping: server.as_ref().and_then(|server| server.ping(entity)),
+        })
+        .collect();
+    rows.sort_by_key(|row| {
+        (
+            team_order(row.team),
+            Reverse(row.stats.score),
+            Reverse(row.stats.kills),
+        )
+    });
+    scoreboard.rows = rows;
+}
+
+fn expire_kills(time: Res<Time>, mut feed: ResMut<KillFeed>) {
+    let now = time.seconds_since_startup();
+    let expired = feed
+        .kills
+        .front()
+        .map_or(false, |(_, added)| now - added > KILL_FEED_TIME);
+    if expired {
+        feed.kills.pop_front();
+    }
+}
+
+struct ScoreboardPanel;
+
+struct KillFeedPanel;
+
+// The bomb is no shop item, its icon is loaded on its own
+struct BombIcon(Handle<ColorMaterial>);
+
+impl FromWorld for BombIcon {
+    fn from_world(world: &mut World) -> Self {
+        let texture: Handle<Texture> = world
+            .get_resource::<AssetServer>()
+            .unwrap()
+            .load("textures/icons/c4.png");
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        BombIcon(materials.add(texture.into()))
+    }
+}
+
+const COLUMNS: [(&str, f32); 7] = [
+    ("Name", 200.0),
+    ("Team", 60.0),
+    ("K", 50.0),
+    ("D", 50.0),
+    ("A", 50.0),
+    ("Score", 70.0),
+    ("Ping", 70.0),
+];
+
+fn name_color(team: Option<Team>) -> Color {
+    team.map_or(Color::WHITE, |team| team.color())
+}
+
+fn spawn_row(
+    parent: &mut ChildBuilder,
+    cells: Vec<String>,
+    color: Color,
+    font: &Handle<Font>,
+    button_materials: &ButtonMaterials,
+) {
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .with_children(|row| {
+            for (cell, (_, width)) in cells.iter().zip(COLUMNS.iter()) {
+                row.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(*width), Val::Px(28.0)),
+                        ..Default::default()
+                    },
+                    material: button_materials.none.clone(),
+                    ..Default::default()
+                })
+                .with_children(|column| {
+                    let mut text = text_bundle(cell, font.clone(), 22.0);
+                    text.text.sections[0].style.color = color;
+                    column.spawn_bundle(text);
+                });
+            }
+        });
+}
+
+fn spawn_scoreboard(
+    commands: &mut Commands,
+    scoreboard: &Scoreboard,
+    font: &Handle<Font>,
+    button_materials: &ButtonMaterials,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(25.0),
+                    top: Val::Px(80.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(12.0)),
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(ScoreboardPanel)
+        .with_children(|parent| {
+            let header = COLUMNS.iter().map(|(title, _)| title.to_string()).collect();
+            let header_color = Color::rgb(0.7, 0.7, 0.7);
+            spawn_row(parent, header, header_color, font, button_materials);
+            for row in scoreboard.rows.iter() {
+                let team = match row.team {
+                    Some(Team::Terrorists) => "T",
+                    Some(Team::CounterTerrorists) => "CT",
+                    None => "-",
+                };
+                let cells = vec![
+                    row.name.clone(),
+                    team.to_string(),
+                    row.stats.kills.to_string(),
+                    row.stats.deaths.to_string(),
+                    row.stats.assists.to_string(),
+                    row.stats.score.to_string(),
+                    row.ping.map_or("-".to_string(), |ping| ping.to_string()),
+                ];
+                spawn_row(parent, cells, name_color(row.team), font, button_materials);
+            }
+        });
+}
+
+// Open while the key is held, rebuilt whenever the scoreboard changes
+fn show_scoreboard(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    scoreboard: Res<Scoreboard>,
+    panels: Query<Entity, With<ScoreboardPanel>>,
+) {
+    if let Some(panel) = panels.iter().next() {
+        if actions.show_scoreboard && !scoreboard.is_changed() {
+            return;
+        }
+        commands.entity(panel).despawn_recursive();
+    }
+    if actions.show_scoreboard {
+        spawn_scoreboard(
+            &mut commands,
+            &scoreboard,
+            &fonts.fira_sans,
+            &button_materials,
+        );
+    }
+}
+
+fn spawn_kill_feed(mut commands: Commands, button_materials: Res<ButtonMaterials>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(16.0),
+                    top: Val::Px(16.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: button_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(KillFeedPanel);
+}
+
+fn name_text(name: &str, team: Option<Team>, font: &Handle<Font>) -> TextBundle {
+    let mut text = text_bundle(name, font.clone(), 22.0);
+    text.text.sections[0].style.color = name_color(team);
+    text
+}
+
+// Killer and victim in their team's color with the icon of the weapon between them
+fn spawn_notice(
+    entry: &mut ChildBuilder,
+    notice: &KillNotice,
+    icon: Option<Handle<ColorMaterial>>,
+    font: &Handle<Font>,
+) {
+    if let Some(killer) = &notice.killer {
+        entry.spawn_bundle(name_text(killer, notice.killer_team, font));
+    }
+    if let Some(icon) = icon {
+        entry.spawn_bundle(ImageBundle {
+            style: Style {
+                size: Size::new(Val::Px(32.0), Val::Px(16.0)),
+                margin: Rect {
+                    left: Val::Px(6.0),
+                    right: Val::Px(6.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: icon,
+            ..Default::default()
+        });
+    }
+    entry.spawn_bundle(name_text(&notice.victim, notice.victim_team, font));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_kill_feed(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    data: Res<DataAssets>,
+    shops: Res<Assets<ShopAsset>>,
+    bomb_icon: Res<BombIcon>,
+    feed: Res<KillFeed>,
+    panels: Query<(Entity, Option<&Children>), With<KillFeedPanel>>,
+) {
+    if !feed.is_changed() {
+        return;
+    }
+    let (panel, children) = match panels.single() {
+        Ok(panel) => panel,
+        Err(_) => return,
+    };
+    let icons = shops.get(&data.shop).map(|shop| &shop.icons);
+    let icon = |weapon: &str| match weapon {
+        "C4" => Some(bomb_icon.0.clone()),
+        _ => icons.and_then(|icons| icons.get(weapon)).cloned(),
+    };
+    for child in children.iter().flat_map(|children| children.iter()) {
+        commands.entity(*child).despawn_recursive();
+    }
+    commands.entity(panel).with_children(|parent| {
+        for (notice, _) in feed.kills.iter() {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(2.0)),
+                        padding: Rect::all(Val::Px(4.0)),
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: button_materials.panel.clone(),
+                    ..Default::default()
+                })
+                .with_children(|entry| {
+                    let icon = notice.weapon.as_deref().and_then(&icon);
+                    spawn_notice(entry, notice, icon, &fonts.fira_sans);
+                });
+        }
+    });
+}
+
+// Kill numbers start over in the next match, old ones would hide its kills
+fn reset_scores(mut scoreboard: ResMut<Scoreboard>, mut feed: ResMut<KillFeed>) {
+    *scoreboard = Scoreboard::default();
+    *feed = KillFeed::default();
+}
+
+fn despawn_scoreboard(
+    mut commands: Commands,
+    panels: Query<Entity, Or<(With<ScoreboardPanel>, With<KillFeedPanel>)>>,
+) {
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
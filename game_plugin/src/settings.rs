@@ -0,0 +1,638 @@
+use crate::audio::SoundChannel;
+use crate::consts::{MAX_NAME_LENGTH, SETTINGS_FILE};
+use crate::loading::FontAssets;
+use crate::ui::{text_bundle, ButtonMaterials, Gamepads, MenuButton, MenuSelection};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+pub struct SettingsPlugin;
+
+// The settings screen is pushed on top of the main or the pause menu.
+// Changes apply right away and are written to the settings file when it is closed.
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SettingsEdit>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Settings).with_system(spawn_settings.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(edit_entry.system().before("activate"))
+                    .with_system(activate_entry.system().label("activate"))
+                    .with_system(adjust_entry.system())
+                    .with_system(update_rows.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Settings).with_system(close_settings.system()),
+            );
+    }
+}
+
+// Everything the player can change, stored in the settings file.
+// Missing fields keep their defaults, so older files still load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub name: String,
+    pub video: VideoSettings,
+    pub audio: AudioSettings,
+    pub controls: Controls,
+    pub crosshair: CrosshairSettings,
+    pub minimap: MinimapSettings,
+    pub camera: CameraSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            name: "Player".to_string(),
+            video: VideoSettings::default(),
+            audio: AudioSettings::default(),
+            controls: Controls::default(),
+            crosshair: CrosshairSettings::default(),
+            minimap: MinimapSettings::default(),
+            camera: CameraSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub resolution: (f32, f32),
+    pub fullscreen: bool,
+    pub vsync: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            resolution: (1024.0, 720.0),
+            fullscreen: false,
+            vsync: true,
+        }
+    }
+}
+
+// Volumes from 0 to 1, every channel is also scaled by the master volume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub effects: f32,
+    pub interface: f32,
+}
+
+impl AudioSettings {
+    pub fn volume(&self, channel: SoundChannel) -> f32 {
+        let volume = match channel {
+            SoundChannel::Effects => self.effects,
+            SoundChannel::Interface => self.interface,
+        };
+        self.master * volume
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            effects: 1.0,
+            interface: 1.0,
+        }
+    }
+}
+
+// Movement also works with the arrow keys, whatever is bound here
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Controls {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub jump: KeyCode,
+    pub interact: KeyCode,
+    pub buy: KeyCode,
+    pub throw: KeyCode,
+    pub scoreboard: KeyCode,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            up: KeyCode::W,
+            down: KeyCode::S,
+            left: KeyCode::A,
+            right: KeyCode::D,
+            jump: KeyCode::Space,
+            interact: KeyCode::E,
+            buy: KeyCode::B,
+            throw: KeyCode::G,
+            scoreboard: KeyCode::Tab,
+        }
+    }
+}
+
+impl Controls {
+    const NAMES: [&'static str; 9] = [
+        "Move up",
+        "Move down",
+        "Move left",
+        "Move right",
+        "Jump",
+        "Use",
+        "Buy",
+        "Throw",
+        "Scoreboard",
+    ];
+
+    fn key(&self, index: usize) -> KeyCode {
+        match index {
+            0 => self.up,
+            1 => self.down,
+            2 => self.left,
+            3 => self.right,
+            4 => self.jump,
+            5 => self.interact,
+            6 => self.buy,
+            7 => self.throw,
+            _ => self.scoreboard,
+        }
+    }
+
+    fn key_mut(&mut self, index: usize) -> &mut KeyCode {
+        match index {
+            0 => &mut self.up,
+            1 => &mut self.down,
+            2 => &mut self.left,
+            3 => &mut self.right,
+            4 => &mut self.jump,
+            5 => &mut self.interact,
+            6 => &mut self.buy,
+            7 => &mut self.throw,
+            _ => &mut self.scoreboard,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrosshairSettings {
+    // Length of the lines in pixels
+    pub size: f32,
+    pub thickness: f32,
+    pub color: [f32; 3],
+}
+
+impl Default for CrosshairSettings {
+    fn default() -> Self {
+        Self {
+            size: 16.0,
+            thickness: 2.0,
+            color: CROSSHAIR_COLORS[0],
+        }
+    }
+}
+
+impl CrosshairSettings {
+    pub fn color(&self) -> Color {
+        Color::rgb(self.color[0], self.color[1], self.color[2])
+    }
+}
+
+// A fixed minimap shows the whole map, a rotating one follows the local player
+// and turns so the aim points up
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinimapSettings {
+    pub rotate: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    // Moves the view a bit towards where the player aims
+    pub look_ahead: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self { look_ahead: true }
+    }
+}
+
+const RESOLUTIONS: [(f32, f32); 5] = [
+    (1024.0, 720.0),
+    (1280.0, 720.0),
+    (1600.0, 900.0),
+    (1920.0, 1080.0),
+    (2560.0, 1440.0),
+];
+const CROSSHAIR_COLORS: [[f32; 3]; 5] = [
+    [0.2, 1.0, 0.2],
+    [1.0, 1.0, 1.0],
+    [1.0, 1.0, 0.2],
+    [0.2, 1.0, 1.0],
+    [1.0, 0.2, 0.2],
+];
+const VOLUME_STEP: f32 = 0.1;
+const CROSSHAIR_SIZE_STEP: f32 = 4.0;
+const MAX_CROSSHAIR_SIZE: f32 = 40.0;
+
+impl Settings {
+    // The game has to start without a settings file, and with a broken one
+    pub fn load() -> Self {
+        let text = match fs::read_to_string(SETTINGS_FILE) {
+            Ok(text) => text,
+            Err(_) => return Settings::default(),
+        };
+        ron::from_str(&text).unwrap_or_else(|error| {
+            // The settings are loaded before the app is built, so the log plugin is not set up yet
+            eprintln!("Ignoring {}: {}", SETTINGS_FILE, error);
+            Settings::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|error| error.to_string())
+            .and_then(|text| fs::write(SETTINGS_FILE, text).map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            error!("Failed to save {}: {}", SETTINGS_FILE, error);
+        }
+    }
+}
+
+// Like the game options, plugins read the settings while they are built
+pub fn game_settings(app: &mut AppBuilder) -> Settings {
+    app.world()
+        .get_resource::<Settings>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn apply_video_settings(video: &VideoSettings, windows: &mut Windows) {
+    if let Some(window) = windows.get_primary_mut() {
+        let (width, height) = video.resolution;
+        window.set_resolution(width, height);
+        window.set_vsync(video.vsync);
+        window.set_mode(if video.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SettingsEntry {
+    Name,
+    Resolution,
+    Fullscreen,
+    Vsync,
+    MasterVolume,
+    EffectsVolume,
+    InterfaceVolume,
+    CrosshairSize,
+    CrosshairColor,
+    MinimapRotation,
+    LookAhead,
+    Binding(usize),
+    Back,
+}
+
+impl SettingsEntry {
+    fn all() -> Vec<SettingsEntry> {
+        let mut entries = vec![
+            SettingsEntry::Name,
+            SettingsEntry::Resolution,
+            SettingsEntry::Fullscreen,
+            SettingsEntry::Vsync,
+            SettingsEntry::MasterVolume,
+            SettingsEntry::EffectsVolume,
+            SettingsEntry::InterfaceVolume,
+            SettingsEntry::CrosshairSize,
+            SettingsEntry::CrosshairColor,
+            SettingsEntry::MinimapRotation,
+            SettingsEntry::LookAhead,
+        ];
+        entries.extend((0..Controls::NAMES.len()).map(SettingsEntry::Binding));
+        entries.push(SettingsEntry::Back);
+        entries
+    }
+
+    fn text(&self, settings: &Settings, edit: Option<Edit>) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        let percent = |volume: f32| format!("{:.0}%", volume * 100.0);
+        match *self {
+            SettingsEntry::Name if edit == Some(Edit::Name) => format!("Name: {}_", settings.name),
+            SettingsEntry::Name => format!("Name: {}", settings.name),
+            SettingsEntry::Resolution => {
+                let (width, height) = settings.video.resolution;
+                format!("Resolution: {}x{}", width, height)
+            }
+            SettingsEntry::Fullscreen => {
+                format!("Fullscreen: {}", on_off(settings.video.fullscreen))
+            }
+            SettingsEntry::Vsync => format!("VSync: {}", on_off(settings.video.vsync)),
+            SettingsEntry::MasterVolume => {
+                format!("Master volume: {}", percent(settings.audio.master))
+            }
+            SettingsEntry::EffectsVolume => {
+                format!("Effects volume: {}", percent(settings.audio.effects))
+            }
+            SettingsEntry::InterfaceVolume => {
+                format!("Interface volume: {}", percent(settings.audio.interface))
+            }
+            SettingsEntry::CrosshairSize => format!("Crosshair size: {}", settings.crosshair.size),
+            SettingsEntry::CrosshairColor => {
+                let index = CROSSHAIR_COLORS
+                    .iter()
+                    .position(|color| *color == settings.crosshair.color);
+                match index {
+                    Some(index) => format!("Crosshair color: {}", index + 1),
+                    None => "Crosshair color: custom".to_string(),
+                }
+            }
+            SettingsEntry::MinimapRotation => {
+                let rotation = if settings.minimap.rotate {
+                    "Rotating"
+                } else {
+                    "Fixed"
+                };
+                format!("Minimap: {}", rotation)
+            }
+            SettingsEntry::LookAhead => {
+                format!("Camera look-ahead: {}", on_off(settings.camera.look_ahead))
+            }
+            SettingsEntry::Binding(index) if edit == Some(Edit::Binding(index)) => {
+                format!("{}: press a key", Controls::NAMES[index])
+            }
+            SettingsEntry::Binding(index) => {
+                format!(
+                    "{}: {:?}",
+                    Controls::NAMES[index],
+                    settings.controls.key(index)
+                )
+            }
+            SettingsEntry::Back => "Back".to_string(),
+        }
+    }
+
+    // Steps a value forwards or backwards, toggles ignore the direction.
+    // Returns whether the window has to be updated.
+    fn change(&self, settings: &mut Settings, step: i32) -> bool {
+        let volume = |volume: &mut f32| {
+            let steps = (1.0 / VOLUME_STEP).round() as i32;
+            let current = (*volume / VOLUME_STEP).round() as i32;
+            *volume = (current + step).rem_euclid(steps + 1) as f32 * VOLUME_STEP;
+        };
+        match self {
+            SettingsEntry::Resolution => {
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|resolution| *resolution == settings.video.resolution)
+                    .unwrap_or(0) as i32;
+                let next = (current + step).rem_euclid(RESOLUTIONS.len() as i32);
+                settings.video.resolution = RESOLUTIONS[next as usize];
+                return true;
+            }
+            SettingsEntry::Fullscreen => {
+                settings.video.fullscreen = !settings.video.fullscreen;
+                return true;
+            }
+            SettingsEntry::Vsync => {
+                settings.video.vsync = !settings.video.vsync;
+                return true;
+            }
+            SettingsEntry::MasterVolume => volume(&mut settings.audio.master),
+            SettingsEntry::EffectsVolume => volume(&mut settings.audio.effects),
+            SettingsEntry::InterfaceVolume => volume(&mut settings.audio.interface),
+            SettingsEntry::CrosshairSize => {
+                let size = settings.crosshair.size + step as f32 * CROSSHAIR_SIZE_STEP;
+                settings.crosshair.size = if size > MAX_CROSSHAIR_SIZE {
+                    CROSSHAIR_SIZE_STEP
+                } else if size < CROSSHAIR_SIZE_STEP {
+                    MAX_CROSSHAIR_SIZE
+                } else {
+                    size
+                };
+            }
+            SettingsEntry::CrosshairColor => {
+                let current = CROSSHAIR_COLORS
+                    .iter()
+                    .position(|color| *color == settings.crosshair.color)
+                    .unwrap_or(0) as i32;
+                let next = (current + step).rem_euclid(CROSSHAIR_COLORS.len() as i32);
+                settings.crosshair.color = CROSSHAIR_COLORS[next as usize];
+            }
+            SettingsEntry::MinimapRotation => settings.minimap.rotate = !settings.minimap.rotate,
+            SettingsEntry::LookAhead => settings.camera.look_ahead = !settings.camera.look_ahead,
+            SettingsEntry::Name | SettingsEntry::Binding(_) | SettingsEntry::Back => {}
+        }
+        false
+    }
+}
+
+// Text entry that takes over the keyboard from the menu navigation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Name,
+    Binding(usize),
+}
+
+#[derive(Default)]
+struct SettingsEdit(Option<Edit>);
+
+struct SettingsUi;
+struct SettingsRow(SettingsEntry);
+
+fn spawn_settings(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    settings: Res<Settings>,
+    mut selection: ResMut<MenuSelection>,
+    mut edit: ResMut<SettingsEdit>,
+) {
+    *selection = MenuSelection::default();
+    edit.0 = None;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: button_materials.panel.clone(),
+            ..Default::default()
+        })
+        .insert(SettingsUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(text_bundle("Settings", fonts.fira_sans.clone(), 32.0));
+            for (index, entry) in SettingsEntry::all().into_iter().enumerate() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(360.0), Val::Px(26.0)),
+                            margin: Rect::all(Val::Px(2.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        material: button_materials.normal.clone(),
+                        ..Default::default()
+                    })
+                    .insert(MenuButton(index))
+                    .with_children(|button| {
+                        let text = entry.text(&settings, None);
+                        button
+                            .spawn_bundle(text_bundle(&text, fonts.fira_sans.clone(), 18.0))
+                            .insert(SettingsRow(entry));
+                    });
+            }
+        });
+}
+
+fn activate_entry(
+    mut selection: ResMut<MenuSelection>,
+    mut settings: ResMut<Settings>,
+    mut edit: ResMut<SettingsEdit>,
+    mut state: ResMut<State<GameState>>,
+    mut windows: ResMut<Windows>,
+) {
+    let entry = match selection.activated.take() {
+        Some(index) => SettingsEntry::all()[index],
+        None => return,
+    };
+    match entry {
+        SettingsEntry::Name => edit.0 = Some(Edit::Name),
+        SettingsEntry::Binding(index) => edit.0 = Some(Edit::Binding(index)),
+        SettingsEntry::Back => state.pop().unwrap(),
+        _ => {
+            if entry.change(&mut settings, 1) {
+                apply_video_settings(&settings.video, &mut windows);
+            }
+        }
+    }
+    selection.locked = edit.0.is_some();
+}
+
+// Left and right step through the values of the selected entry
+fn adjust_entry(
+    selection: Res<MenuSelection>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut settings: ResMut<Settings>,
+    mut windows: ResMut<Windows>,
+) {
+    if selection.locked {
+        return;
+    }
+    let pad_pressed = |button: GamepadButtonType| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton(*gamepad, button)))
+    };
+    let step =
+        if keyboard_input.just_pressed(KeyCode::Left) || pad_pressed(GamepadButtonType::DPadLeft) {
+            -1
+        } else if keyboard_input.just_pressed(KeyCode::Right)
+            || pad_pressed(GamepadButtonType::DPadRight)
+        {
+            1
+        } else {
+            return;
+        };
+    let entry = SettingsEntry::all()[selection.selected];
+    if entry.change(&mut settings, step) {
+        apply_video_settings(&settings.video, &mut windows);
+    }
+}
+
+// Escape leaves the screen, or stops editing an entry. Keys that end an edit
+// are consumed so the menu navigation does not act on them in the same frame.
+fn edit_entry(
+    mut characters: EventReader<ReceivedCharacter>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut edit: ResMut<SettingsEdit>,
+    mut selection: ResMut<MenuSelection>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let typed: Vec<char> = characters.iter().map(|event| event.char).collect();
+    let pressed: Vec<KeyCode> = keyboard_input.get_just_pressed().copied().collect();
+    let finished = match edit.0 {
+        None => {
+            if keyboard_input.just_pressed(KeyCode::Escape) {
+                keyboard_input.reset(KeyCode::Escape);
+                state.pop().unwrap();
+            }
+            return;
+        }
+        Some(Edit::Name) => {
+            for c in typed.into_iter().filter(|c| !c.is_control()) {
+                if settings.name.chars().count() < MAX_NAME_LENGTH {
+                    settings.name.push(c);
+                }
+            }
+            if keyboard_input.just_pressed(KeyCode::Back) {
+                settings.name.pop();
+            }
+            let finished = pressed
+                .into_iter()
+                .find(|key| *key == KeyCode::Return || *key == KeyCode::Escape);
+            if finished.is_some() && settings.name.trim().is_empty() {
+                settings.name = Settings::default().name;
+            }
+            finished
+        }
+        Some(Edit::Binding(index)) => {
+            let key = pressed.into_iter().next();
+            match key {
+                Some(KeyCode::Escape) | None => {}
+                Some(key) => *settings.controls.key_mut(index) = key,
+            }
+            key
+        }
+    };
+    if let Some(key) = finished {
+        keyboard_input.reset(key);
+        edit.0 = None;
+        selection.locked = false;
+    }
+}
+
+fn update_rows(
+    settings: Res<Settings>,
+    edit: Res<SettingsEdit>,
+    mut rows: Query<(&SettingsRow, &mut Text)>,
+) {
+    if !settings.is_changed() && !edit.is_changed() {
+        return;
+    }
+    for (row, mut text) in rows.iter_mut() {
+        text.sections[0].value = row.0.text(&settings, edit.0);
+    }
+}
+
+fn close_settings(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut selection: ResMut<MenuSelection>,
+    ui: Query<Entity, With<SettingsUi>>,
+) {
+    selection.locked = false;
+    settings.save();
+    for entity in ui.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
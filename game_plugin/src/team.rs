@@ -0,0 +1,129 @@
+use crate::map::Map;
+use crate::options::GameRng;
+use crate::player::Player;
+use crate::round::{HalftimeEvent, RoundStartEvent};
+use crate::{gameplay_update, Role};
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub struct TeamPlugin;
+
+// Players joining without a team are put on the smaller side and moved into its spawn area.
+// Spawn areas are read from the Spawn tiles of the current map.
+impl Plugin for TeamPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(gameplay_update(Role::Everyone).with_system(apply_team_color.system()))
+            .add_system_set(
+                gameplay_update(Role::Authority)
+                    .with_system(assign_team.system())
+                    .with_system(spawn_joined.system())
+                    .with_system(swap_sides.system())
+                    .with_system(move_to_spawn.system()),
+            );
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Team {
+    Terrorists,
+    CounterTerrorists,
+}
+
+impl Team {
+    pub fn opponent(&self) -> Team {
+        match self {
+            Team::Terrorists => Team::CounterTerrorists,
+            Team::CounterTerrorists => Team::Terrorists,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Team::Terrorists => Color::rgb(1.0, 0.75, 0.45),
+            Team::CounterTerrorists => Color::rgb(0.55, 0.7, 1.0),
+        }
+    }
+}
+
+fn assign_team(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    joined: Query<Entity, (Added<Player>, Without<Team>)>,
+    players: Query<&Team, With<Player>>,
+) {
+    let mut terrorists = players
+        .iter()
+        .filter(|team| **team == Team::Terrorists)
+        .count();
+    let mut counter_terrorists = players.iter().count() - terrorists;
+
+    for entity in joined.iter() {
+        let team = if terrorists < counter_terrorists
+            || (terrorists == counter_terrorists && rng.0.gen_bool(0.5))
+        {
+            terrorists += 1;
+            Team::Terrorists
+        } else {
+            counter_terrorists += 1;
+            Team::CounterTerrorists
+        };
+        commands.entity(entity).insert(team);
+    }
+}
+
+fn spawn_joined(
+    map: Option<Res<Map>>,
+    mut rng: ResMut<GameRng>,
+    mut joined: Query<(&Team, &mut Transform), Added<Team>>,
+) {
+    if let Some(map) = map {
+        for (team, mut transform) in joined.iter_mut() {
+            place_in_spawn(&map, &mut rng, *team, &mut transform);
+        }
+    }
+}
+
+fn move_to_spawn(
+    map: Option<Res<Map>>,
+    mut rng: ResMut<GameRng>,
+    mut round_start: EventReader<RoundStartEvent>,
+    mut players: Query<(&Team, &mut Transform), With<Player>>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    if round_start.iter().last().is_none() {
+        return;
+    }
+    for (team, mut transform) in players.iter_mut() {
+        place_in_spawn(&map, &mut rng, *team, &mut transform);
+    }
+}
+
+fn place_in_spawn(map: &Map, rng: &mut GameRng, team: Team, transform: &mut Transform) {
+    if let Some(position) = map.spawn_area(team).choose(&mut rng.0) {
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+fn swap_sides(
+    mut halftime: EventReader<HalftimeEvent>,
+    mut players: Query<&mut Team, With<Player>>,
+) {
+    if halftime.iter().last().is_none() {
+        return;
+    }
+    for mut team in players.iter_mut() {
+        *team = team.opponent();
+    }
+}
+
+fn apply_team_color(mut players: Query<(&Team, &mut TextureAtlasSprite), Changed<Team>>) {
+    for (team, mut sprite) in players.iter_mut() {
+        sprite.color = team.color();
+    }
+}
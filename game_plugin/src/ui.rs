@@ -0,0 +1,205 @@
+use crate::audio::{Sound, SoundEvent};
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+pub struct UiPlugin;
+
+// Shared pieces for all screens drawn with bevy_ui
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ButtonMaterials>()
+            .init_resource::<MenuSelection>()
+            .init_resource::<Gamepads>()
+            .add_startup_system(spawn_ui_camera.system())
+            .add_system(track_gamepads.system())
+            .add_system(update_button_colors.system())
+            .add_system(navigate_menu.system())
+            .add_system(highlight_selected.system());
+    }
+}
+
+pub struct ButtonMaterials {
+    pub normal: Handle<ColorMaterial>,
+    pub hovered: Handle<ColorMaterial>,
+    pub pressed: Handle<ColorMaterial>,
+    pub panel: Handle<ColorMaterial>,
+    pub none: Handle<ColorMaterial>,
+}
+
+impl FromWorld for ButtonMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        ButtonMaterials {
+            normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+            pressed: materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
+            panel: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into()),
+            none: materials.add(Color::NONE.into()),
+        }
+    }
+}
+
+// Entry of a vertical menu, numbered from the top
+pub struct MenuButton(pub usize);
+
+// The entry of the open menu that is selected and the one that was activated this frame.
+// Menus are used with the mouse, the arrow keys or a gamepad's d-pad.
+#[derive(Default)]
+pub struct MenuSelection {
+    pub selected: usize,
+    pub activated: Option<usize>,
+    // Set while a menu reads the keyboard itself, e.g. for text entry
+    pub locked: bool,
+}
+
+// Bevy only reports gamepads connecting and disconnecting, menus listen to all connected ones
+#[derive(Default)]
+pub struct Gamepads(HashSet<Gamepad>);
+
+impl Gamepads {
+    pub fn iter(&self) -> impl Iterator<Item = &Gamepad> {
+        self.0.iter()
+    }
+}
+
+fn track_gamepads(mut gamepads: ResMut<Gamepads>, mut events: EventReader<GamepadEvent>) {
+    for GamepadEvent(gamepad, event) in events.iter() {
+        match event {
+            GamepadEventType::Connected => {
+                gamepads.0.insert(*gamepad);
+            }
+            GamepadEventType::Disconnected => {
+                gamepads.0.remove(gamepad);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+fn update_button_colors(
+    button_materials: Res<ButtonMaterials>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>),
+        (Changed<Interaction>, With<Button>, Without<MenuButton>),
+    >,
+) {
+    for (interaction, mut material) in interaction_query.iter_mut() {
+        *material = match *interaction {
+            Interaction::Clicked => button_materials.pressed.clone(),
+            Interaction::Hovered => button_materials.hovered.clone(),
+            Interaction::None => button_materials.normal.clone(),
+        };
+    }
+}
+
+pub fn text_bundle(text: &str, font: Handle<Font>, font_size: f32) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            text,
+            TextStyle {
+                font,
+                font_size,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }
+}
+
+pub fn menu_button(
+    parent: &mut ChildBuilder,
+    index: usize,
+    label: &str,
+    font: Handle<Font>,
+    button_materials: &ButtonMaterials,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(240.0), Val::Px(50.0)),
+                margin: Rect::all(Val::Px(6.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .insert(MenuButton(index))
+        .with_children(|button| {
+            button.spawn_bundle(text_bundle(label, font, 32.0));
+        });
+}
+
+fn navigate_menu(
+    mut selection: ResMut<MenuSelection>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut sounds: EventWriter<SoundEvent>,
+    entries: Query<&MenuButton>,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    let count = entries.iter().count();
+    if count == 0 || selection.locked {
+        return;
+    }
+    let pad_pressed = |button: GamepadButtonType| {
+        gamepads
+            .iter()
+            .any(|gamepad| gamepad_input.just_pressed(GamepadButton(*gamepad, button)))
+    };
+
+    if keyboard_input.just_pressed(KeyCode::Up)
+        || keyboard_input.just_pressed(KeyCode::W)
+        || pad_pressed(GamepadButtonType::DPadUp)
+    {
+        selection.selected = (selection.selected + count - 1) % count;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down)
+        || keyboard_input.just_pressed(KeyCode::S)
+        || pad_pressed(GamepadButtonType::DPadDown)
+    {
+        selection.selected = (selection.selected + 1) % count;
+    }
+    selection.selected = selection.selected.min(count - 1);
+    if keyboard_input.just_pressed(KeyCode::Return)
+        || keyboard_input.just_pressed(KeyCode::Space)
+        || pad_pressed(GamepadButtonType::South)
+    {
+        selection.activated = Some(selection.selected);
+        sounds.send(SoundEvent::interface(Sound::Select));
+    }
+
+    for (interaction, button) in buttons.iter() {
+        match interaction {
+            Interaction::Hovered => selection.selected = button.0,
+            Interaction::Clicked => {
+                selection.selected = button.0;
+                selection.activated = Some(button.0);
+                sounds.send(SoundEvent::interface(Sound::Select));
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+// Keyboard and gamepad selection look the same as hovering with the mouse
+fn highlight_selected(
+    selection: Res<MenuSelection>,
+    button_materials: Res<ButtonMaterials>,
+    mut buttons: Query<(&MenuButton, &mut Handle<ColorMaterial>)>,
+) {
+    for (button, mut material) in buttons.iter_mut() {
+        *material = if button.0 == selection.selected {
+            button_materials.hovered.clone()
+        } else {
+            button_materials.normal.clone()
+        };
+    }
+}
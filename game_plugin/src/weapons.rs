@@ -0,0 +1,156 @@
+use crate::animation::{AnimationEvent, FrameEvent};
+use crate::audio::{Sound, SoundEvent};
+use crate::combat::{DamageEvent, DamageSource, Dead};
+use crate::consts::{HITBOX_HALF_SIZE, WEAPON_RANGE};
+use crate::economy::{Inventory, Weapon};
+use crate::map::Map;
+use crate::net::HitboxHistory;
+use crate::player::{Player, PlayerInput};
+use crate::round::Round;
+use crate::{gameplay_update, Role};
+use bevy::prelude::*;
+
+pub struct WeaponsPlugin;
+
+// Weapons are hitscan: a shot hits the first player along the aim that is not behind a wall.
+// On a server the targets are rewound to the moment the shooter saw, see HitboxHistory.
+impl Plugin for WeaponsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            gameplay_update(Role::Authority)
+                .with_system(fire_weapons.system())
+                .with_system(finish_reloads.system()),
+        );
+    }
+}
+
+// Distance along the ray to where it enters the box, if it does within `range`
+fn ray_distance(origin: Vec2, direction: Vec2, range: f32, center: Vec2, half: f32) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = range;
+    for axis in 0..2 {
+        let (origin, direction, center) = (origin[axis], direction[axis], center[axis]);
+        if direction == 0.0 {
+            if (origin - center).abs() > half {
+                return None;
+            }
+            continue;
+        }
+        let a = (center - half - origin) / direction;
+        let b = (center + half - origin) / direction;
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+    if near <= far {
+        Some(near)
+    } else {
+        None
+    }
+}
+
+fn finish_reload(weapon: &mut Weapon) {
+    let reloaded = (weapon.magazine - weapon.ammo).min(weapon.reserve);
+    weapon.reserve -= reloaded;
+    weapon.ammo += reloaded;
+    weapon.reloading = false;
+    weapon.cooldown = 0.0;
+}
+
+// The reload animation decides when the magazine is full again
+fn finish_reloads(mut events: EventReader<AnimationEvent>, mut players: Query<&mut Inventory>) {
+    for event in events.iter() {
+        if event.event != FrameEvent::ReloadComplete {
+            continue;
+        }
+        let mut inventory = match players.get_mut(event.entity) {
+            Ok(inventory) => inventory,
+            Err(_) => continue,
+        };
+        if let Some(weapon) = inventory.weapon.as_mut().filter(|weapon| weapon.reloading) {
+            finish_reload(weapon);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fire_weapons(
+    time: Res<Time>,
+    round: Res<Round>,
+    map: Option<Res<Map>>,
+    history: Option<Res<HitboxHistory>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut shooters: Query<(Entity, &Transform, &PlayerInput, &mut Inventory), Without<Dead>>,
+    targets: Query<(Entity, &Transform), (With<Player>, Without<Dead>)>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    let delta = time.delta_seconds();
+
+    for (entity, transform, input, mut inventory) in shooters.iter_mut() {
+        let weapon = match inventory.weapon.as_mut() {
+            Some(weapon) => weapon,
+            None => continue,
+        };
+        weapon.cooldown = (weapon.cooldown - delta).max(0.0);
+        if !input.fire
+            || weapon.reloading
+            || weapon.cooldown > 0.0
+            || weapon.ammo == 0
+            || !round.phase.allows_movement()
+        {
+            continue;
+        }
+
+        weapon.ammo -= 1;
+        weapon.cooldown = weapon.fire_rate;
+        if weapon.ammo == 0 && weapon.reserve > 0 {
+            weapon.reloading = true;
+        }
+
+        let origin = transform.translation.truncate();
+        sounds.send(SoundEvent::at(Sound::Fire, origin, entity));
+        let direction = match input
+            .aim
+            .map(|aim| aim - origin)
+            .filter(|direction| *direction != Vec2::ZERO)
+        {
+            Some(direction) => direction.normalize(),
+            None => continue,
+        };
+
+        // Remote shooters are checked against what they saw, everybody else against now
+        let hitboxes: Vec<(Entity, Vec2)> = match (history.as_ref(), input.view_tick) {
+            (Some(history), Some(tick)) => history
+                .rewind(tick)
+                .into_iter()
+                .filter(|(target, _)| targets.get(*target).is_ok())
+                .collect(),
+            _ => targets
+                .iter()
+                .map(|(target, transform)| (target, transform.translation.truncate()))
+                .collect(),
+        };
+
+        let hit = hitboxes
+            .iter()
+            .filter(|(target, _)| *target != entity)
+            .filter_map(|(target, center)| {
+                ray_distance(origin, direction, WEAPON_RANGE, *center, HITBOX_HALF_SIZE)
+                    .map(|distance| (*target, distance))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((target, distance)) = hit {
+            if map.line_of_sight(origin, origin + direction * distance) {
+                damage_events.send(DamageEvent {
+                    target,
+                    amount: weapon.damage,
+                    attacker: Some(entity),
+                    source: DamageSource::Weapon(weapon.name.clone()),
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+// Dedicated server: runs the match without a window, renderer, audio or local player.
+//
+//     cargo run --release --bin server -- [--port 27015] [--map map_one] [--bots 4]
+
+use bevy::app::ScheduleRunnerSettings;
+use bevy::asset::AssetPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::App;
+use bevy::transform::TransformPlugin;
+use bevy::MinimalPlugins;
+use game_plugin::cli::Cli;
+use game_plugin::{GamePlugin, NetworkMode};
+use std::time::Duration;
+
+// Frames the server runs per second, movement itself is simulated in fixed steps
+const FRAME_RATE: f64 = 60.0;
+
+// Takes the same arguments as the game, window and connection options are ignored
+fn main() {
+    let cli = Cli::from_env();
+
+    App::build()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+            1.0 / FRAME_RATE,
+        )))
+        .insert_resource(NetworkMode::Dedicated { port: cli.port })
+        .insert_resource(cli.conditions)
+        .insert_resource(cli.options)
+        .insert_resource(cli.config)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(GamePlugin)
+        .run();
+}
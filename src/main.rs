@@ -5,20 +5,38 @@
 use bevy_webgl2;
 
 use bevy::prelude::{App, ClearColor, Color, WindowDescriptor};
+use bevy::window::WindowMode;
 use bevy::DefaultPlugins;
-use game_plugin::GamePlugin;
+use game_plugin::cli::Cli;
+use game_plugin::{GamePlugin, Settings};
 
 fn main() {
+    let cli = Cli::from_env();
+    let settings = Settings::load();
+    let (width, height) = cli.resolution.unwrap_or(settings.video.resolution);
+    let fullscreen = cli.fullscreen.unwrap_or(settings.video.fullscreen);
+
     let mut app = App::build();
     app
         // .insert_resource(Msaa { samples: 4 })
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
         .insert_resource(WindowDescriptor {
-            width: 1024.,
-            height: 720.,
-            title: "Bevy game".to_string(), // ToDo
+            width,
+            height,
+            title: "CS Clone".to_string(),
+            vsync: settings.video.vsync,
+            mode: if fullscreen {
+                WindowMode::BorderlessFullscreen
+            } else {
+                WindowMode::Windowed
+            },
             ..Default::default()
         })
+        .insert_resource(cli.mode)
+        .insert_resource(cli.conditions)
+        .insert_resource(cli.options)
+        .insert_resource(cli.config)
+        .insert_resource(settings)
         .add_plugins(DefaultPlugins)
         .add_plugin(GamePlugin);
 